@@ -6,8 +6,9 @@
 //! - Errors  
 //! - Dispatchable calls
 
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::thread::{self, ThreadId};
 
 /// Account identifier type
 pub type AccountId = u64;
@@ -15,6 +16,27 @@ pub type AccountId = u64;
 pub type Balance = u128;
 /// Block number type
 pub type BlockNumber = u32;
+/// Claimable transfer identifier type
+pub type ClaimId = u64;
+/// Scheduled transfer identifier type
+pub type ScheduleId = u64;
+/// Named reserve identifier type
+pub type ReserveId = [u8; 8];
+/// Identifier of a non-native asset
+pub type AssetId = u32;
+/// Asset id standing for the native balance, whose existential deposit is the pallet-wide one
+pub const NATIVE_ASSET: AssetId = 0;
+/// Client-chosen key identifying an operation that must be applied at most once
+pub type IdempotencyKey = u64;
+
+/// How far ahead `spendable_balance_at` projects, bounding the blocks it steps through
+pub const MAX_PROJECTION_BLOCKS: BlockNumber = 1_000_000;
+/// Reserve used by `reserve`, `unreserve` and deferred deposit reserves
+pub const DEFAULT_RESERVE: ReserveId = *b"default\0";
+/// Reserve holding funds of pending claimable transfers
+pub const CLAIM_RESERVE: ReserveId = *b"claims\0\0";
+
+type NamedReserves = HashMap<AccountId, HashMap<ReserveId, Balance>>;
 
 /// Runtime errors
 #[derive(Debug, Clone, PartialEq)]
@@ -24,6 +46,13 @@ pub enum Error {
     Overflow,
     Underflow,
     InvalidValue,
+    BalanceCapExceeded,
+    LaunchLocked,
+    PostconditionFailed,
+    TransferLimitExceeded,
+    IssuanceFrozen,
+    DuplicateOperation,
+    IssuanceMismatch,
 }
 
 /// Runtime events
@@ -33,25 +62,488 @@ pub enum Event {
     Deposit { who: AccountId, amount: Balance },
     Withdraw { who: AccountId, amount: Balance },
     NewBlock { number: BlockNumber },
+    IssuanceRepaired { old: Balance, new: Balance },
+    ClaimRefunded { id: ClaimId, who: AccountId, amount: Balance },
+    FeeCharged { who: AccountId, amount: Balance },
+    Reserved { who: AccountId, amount: Balance },
+    Unreserved { who: AccountId, amount: Balance },
+    RemainderCollected { who: AccountId, amount: Balance },
+    Interest { who: AccountId, amount: Balance },
+    Haircut { bps: u16, total: Balance },
+    FeeCollected { who: AccountId, amount: Balance },
+    Demurrage { who: AccountId, amount: Balance },
+    Memo { from: AccountId, to: AccountId, memo: Vec<u8> },
+    DustLost { who: AccountId, amount: Balance },
+    DustCollected { from: AccountId, to: AccountId, amount: Balance },
+    AccountReaped { who: AccountId },
+    ScheduledTransferFailed { id: ScheduleId, error: Error },
+    Overdrawn { who: AccountId, amount: Balance },
+    OverdraftRepaid { who: AccountId, amount: Balance },
+    AuthorSettled { who: AccountId, amount: Balance },
+    /// Fees of the closing block credited to the fee sweep treasury
+    FeesSwept { amount: Balance },
+    /// Net change applied to an account by `net_settle`
+    NetSettled { who: AccountId, delta: i128 },
+    AssetDeposit { who: AccountId, asset: AssetId, amount: Balance },
+    /// `amount` of `from_asset` burned for `produced` of `to_asset`
+    AssetConverted { who: AccountId, from_asset: AssetId, to_asset: AssetId, amount: Balance, produced: Balance },
+    AssetWithdraw { who: AccountId, asset: AssetId, amount: Balance },
+    /// Holding of `asset` removed for falling below its existential deposit, burning `dust`
+    AssetReaped { who: AccountId, asset: AssetId, dust: Balance },
+}
+
+impl Event {
+    /// Name of the event variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Transfer { .. } => "Transfer",
+            Event::Deposit { .. } => "Deposit",
+            Event::Withdraw { .. } => "Withdraw",
+            Event::NewBlock { .. } => "NewBlock",
+            Event::IssuanceRepaired { .. } => "IssuanceRepaired",
+            Event::ClaimRefunded { .. } => "ClaimRefunded",
+            Event::FeeCharged { .. } => "FeeCharged",
+            Event::Reserved { .. } => "Reserved",
+            Event::Unreserved { .. } => "Unreserved",
+            Event::RemainderCollected { .. } => "RemainderCollected",
+            Event::Interest { .. } => "Interest",
+            Event::Haircut { .. } => "Haircut",
+            Event::FeeCollected { .. } => "FeeCollected",
+            Event::Demurrage { .. } => "Demurrage",
+            Event::Memo { .. } => "Memo",
+            Event::DustLost { .. } => "DustLost",
+            Event::DustCollected { .. } => "DustCollected",
+            Event::AccountReaped { .. } => "AccountReaped",
+            Event::ScheduledTransferFailed { .. } => "ScheduledTransferFailed",
+            Event::Overdrawn { .. } => "Overdrawn",
+            Event::OverdraftRepaid { .. } => "OverdraftRepaid",
+            Event::AuthorSettled { .. } => "AuthorSettled",
+            Event::FeesSwept { .. } => "FeesSwept",
+            Event::NetSettled { .. } => "NetSettled",
+            Event::AssetDeposit { .. } => "AssetDeposit",
+            Event::AssetConverted { .. } => "AssetConverted",
+            Event::AssetWithdraw { .. } => "AssetWithdraw",
+            Event::AssetReaped { .. } => "AssetReaped",
+        }
+    }
+
+    /// Accounts the event refers to
+    pub fn accounts(&self) -> Vec<AccountId> {
+        match *self {
+            Event::Transfer { from, to, .. } | Event::Memo { from, to, .. } | Event::DustCollected { from, to, .. } => {
+                vec![from, to]
+            }
+            Event::Deposit { who, .. }
+            | Event::Withdraw { who, .. }
+            | Event::ClaimRefunded { who, .. }
+            | Event::FeeCharged { who, .. }
+            | Event::Reserved { who, .. }
+            | Event::Unreserved { who, .. }
+            | Event::RemainderCollected { who, .. }
+            | Event::Interest { who, .. }
+            | Event::FeeCollected { who, .. }
+            | Event::Demurrage { who, .. }
+            | Event::DustLost { who, .. }
+            | Event::AccountReaped { who }
+            | Event::Overdrawn { who, .. }
+            | Event::OverdraftRepaid { who, .. }
+            | Event::AuthorSettled { who, .. }
+            | Event::NetSettled { who, .. }
+            | Event::AssetDeposit { who, .. }
+            | Event::AssetConverted { who, .. }
+            | Event::AssetWithdraw { who, .. }
+            | Event::AssetReaped { who, .. } => vec![who],
+            Event::NewBlock { .. }
+            | Event::IssuanceRepaired { .. }
+            | Event::Haircut { .. }
+            | Event::ScheduledTransferFailed { .. }
+            | Event::FeesSwept { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Summary of the events emitted in a single block
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlockDigest {
+    pub deposited: Balance,
+    pub withdrawn: Balance,
+    pub transferred: Balance,
+    /// Number of events of each variant, keyed by `Event::name`
+    pub event_counts: BTreeMap<&'static str, usize>,
+    pub accounts_touched: BTreeSet<AccountId>,
+}
+
+/// Event tagged with its position in the global event log
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    pub seq: u64,
+    pub event: Event,
+}
+
+/// Dispatchable calls, applied on behalf of an origin account
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    Transfer { to: AccountId, amount: Balance },
+    Deposit { amount: Balance },
+    Withdraw { amount: Balance },
+}
+
+/// How a batch reacts to a failing call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Atomicity {
+    /// Roll back every call if any fails
+    AllOrNothing,
+    /// Keep going past failures
+    BestEffort,
+    /// Stop at the first failure, keeping earlier calls
+    StopOnError,
+}
+
+/// Outcome of a single call within a batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallOutcome {
+    Applied,
+    Failed(Error),
+    /// Applied, then undone because a later call failed
+    RolledBack,
+    /// Not attempted because an earlier call failed
+    Skipped,
+}
+
+/// Per-call outcomes of a batch, in call order
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub outcomes: Vec<CallOutcome>,
+}
+
+/// Where the remaining balance of a reaped account goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DustPolicy {
+    Burn,
+    /// Credit the dust to the given account
+    Transfer(AccountId),
+}
+
+/// What to do with an event payload larger than the configured maximum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadPolicy {
+    /// Fail the operation with `Error::InvalidValue`
+    Reject,
+    /// Keep only the first permitted bytes
+    Truncate,
+}
+
+/// Fee charged to the sender of a transfer, burned unless fee collectors are set
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeConfig {
+    None,
+    Flat(Balance),
+    /// Basis points of the transferred amount, rounded down unless a remainder account collects the
+    /// rounding difference
+    Percentage(u16),
+    /// `(threshold, fee)` pairs; the fee of the highest threshold not above the amount applies
+    Tiered(Vec<(Balance, Balance)>),
+}
+
+/// Aggregate balance statistics gathered in a single pass
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Statistics {
+    pub account_count: usize,
+    pub total_issuance: Balance,
+    pub mean_balance: Balance,
+    /// Mean of the two middle balances when the account count is even
+    pub median_balance: Balance,
+    pub max_balance: Balance,
+    pub min_nonzero_balance: Option<Balance>,
+}
+
+/// Exported account state that can be loaded into another pallet
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StateSnapshot {
+    pub balances: HashMap<AccountId, Balance>,
+    pub reserved: HashMap<AccountId, Balance>,
+    /// Named reserves making up `reserved`; any reserved amount they don't account for is imported into
+    /// `DEFAULT_RESERVE`
+    pub named_reserves: HashMap<AccountId, HashMap<ReserveId, Balance>>,
+    pub seen_keys: HashSet<IdempotencyKey>,
+}
+
+/// How to combine an account present both in the pallet and in an imported snapshot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    Replace,
+    Sum,
+    KeepExisting,
+}
+
+/// Linear vesting schedule locking part of an account's free balance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VestingSchedule {
+    pub locked: Balance,
+    pub per_block: Balance,
+    pub starting_block: BlockNumber,
+}
+
+impl VestingSchedule {
+    /// Amount still locked at `block`
+    pub fn locked_at(&self, block: BlockNumber) -> Balance {
+        let elapsed = Balance::from(block.saturating_sub(self.starting_block));
+        self.locked.saturating_sub(self.per_block.saturating_mul(elapsed))
+    }
+}
+
+/// Transfer held in reserve until the recipient claims it or it expires
+#[derive(Debug, Clone)]
+struct PendingClaim {
+    from: AccountId,
+    to: AccountId,
+    amount: Balance,
+    expires_at: BlockNumber,
+}
+
+/// Transfer waiting to be executed by `next_block`
+#[derive(Debug, Clone)]
+struct ScheduledTransfer {
+    id: ScheduleId,
+    from: AccountId,
+    to: AccountId,
+    amount: Balance,
+    at: BlockNumber,
+    priority: u8,
+}
+
+// Serializes mutating operations so a rolled-back transaction can only undo its own writes. Reentrant so
+// composite operations can call other mutating operations on the same thread.
+struct MutationLock {
+    owner: Mutex<Option<(ThreadId, usize)>>,
+    released: Condvar,
+}
+
+impl MutationLock {
+    fn new() -> Self {
+        Self { owner: Mutex::new(None), released: Condvar::new() }
+    }
+
+    fn lock(&self) -> MutationGuard<'_> {
+        let current = thread::current().id();
+        let mut owner = self.owner.lock().unwrap();
+        loop {
+            match owner.as_mut() {
+                None => *owner = Some((current, 1)),
+                Some((id, depth)) if *id == current => *depth += 1,
+                Some(_) => {
+                    owner = self.released.wait(owner).unwrap();
+                    continue;
+                }
+            }
+            return MutationGuard { lock: self };
+        }
+    }
+}
+
+struct MutationGuard<'a> {
+    lock: &'a MutationLock,
+}
+
+impl Drop for MutationGuard<'_> {
+    fn drop(&mut self) {
+        let mut owner = self.lock.owner.lock().unwrap();
+        if let Some((_, depth)) = owner.as_mut() {
+            *depth -= 1;
+            if *depth == 0 {
+                *owner = None;
+                self.lock.released.notify_one();
+            }
+        }
+    }
 }
 
 /// Storage for the runtime
 pub struct Storage {
+    mutations: MutationLock,
     balances: RwLock<HashMap<AccountId, Balance>>,
+    reserved: RwLock<HashMap<AccountId, Balance>>,
+    named_reserves: RwLock<NamedReserves>,
     total_issuance: RwLock<Balance>,
     block_number: RwLock<BlockNumber>,
-    events: RwLock<Vec<Event>>,
+    events: RwLock<Vec<EventRecord>>,
+    next_event_seq: RwLock<u64>,
+    claims: RwLock<HashMap<ClaimId, PendingClaim>>,
+    next_claim_id: RwLock<ClaimId>,
+    creation_order: RwLock<Vec<AccountId>>,
+    issuance_history: RwLock<Vec<Balance>>,
+    issuance_frozen: RwLock<bool>,
+    interest_earned: RwLock<HashMap<AccountId, Balance>>,
+    fee_collectors: RwLock<Vec<(AccountId, u32)>>,
+    vesting: RwLock<HashMap<AccountId, VestingSchedule>>,
+    pending_reserves: RwLock<HashMap<AccountId, Balance>>,
+    linked_accounts: RwLock<HashMap<AccountId, Vec<AccountId>>>,
+    scheduled: RwLock<Vec<ScheduledTransfer>>,
+    next_schedule_id: RwLock<ScheduleId>,
+    seen_keys: RwLock<HashSet<IdempotencyKey>>,
+    overdrafts: RwLock<HashMap<AccountId, Balance>>,
+    author: RwLock<Option<AccountId>>,
+    block_fees: RwLock<Balance>,
+    // Fees earned by each recipient that could not be credited yet
+    pending_fees: RwLock<BTreeMap<AccountId, Balance>>,
+    tags: RwLock<HashMap<String, BTreeSet<AccountId>>>,
+    // In ten-thousandths of a unit
+    remainder_dust: RwLock<Balance>,
+    asset_balances: RwLock<HashMap<(AccountId, AssetId), Balance>>,
+    asset_issuance: RwLock<HashMap<AssetId, Balance>>,
+    // `(from, to)` to `(numerator, denominator)`
+    conversion_rates: RwLock<HashMap<(AssetId, AssetId), (Balance, Balance)>>,
+    asset_existential_deposits: RwLock<HashMap<AssetId, Balance>>,
 }
 
 impl Storage {
     pub fn new() -> Self {
         Self {
+            mutations: MutationLock::new(),
             balances: RwLock::new(HashMap::new()),
+            reserved: RwLock::new(HashMap::new()),
+            named_reserves: RwLock::new(HashMap::new()),
             total_issuance: RwLock::new(0),
             block_number: RwLock::new(0),
             events: RwLock::new(Vec::new()),
+            next_event_seq: RwLock::new(0),
+            claims: RwLock::new(HashMap::new()),
+            next_claim_id: RwLock::new(0),
+            creation_order: RwLock::new(Vec::new()),
+            issuance_history: RwLock::new(Vec::new()),
+            issuance_frozen: RwLock::new(false),
+            interest_earned: RwLock::new(HashMap::new()),
+            fee_collectors: RwLock::new(Vec::new()),
+            vesting: RwLock::new(HashMap::new()),
+            pending_reserves: RwLock::new(HashMap::new()),
+            linked_accounts: RwLock::new(HashMap::new()),
+            scheduled: RwLock::new(Vec::new()),
+            next_schedule_id: RwLock::new(0),
+            seen_keys: RwLock::new(HashSet::new()),
+            overdrafts: RwLock::new(HashMap::new()),
+            author: RwLock::new(None),
+            block_fees: RwLock::new(0),
+            pending_fees: RwLock::new(BTreeMap::new()),
+            tags: RwLock::new(HashMap::new()),
+            remainder_dust: RwLock::new(0),
+            asset_balances: RwLock::new(HashMap::new()),
+            asset_issuance: RwLock::new(HashMap::new()),
+            conversion_rates: RwLock::new(HashMap::new()),
+            asset_existential_deposits: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+/// Point-in-time copy of storage used to roll back failed operations
+struct StorageSnapshot {
+    balances: HashMap<AccountId, Balance>,
+    reserved: HashMap<AccountId, Balance>,
+    named_reserves: NamedReserves,
+    total_issuance: Balance,
+    block_number: BlockNumber,
+    events: Vec<EventRecord>,
+    next_event_seq: u64,
+    claims: HashMap<ClaimId, PendingClaim>,
+    next_claim_id: ClaimId,
+    creation_order: Vec<AccountId>,
+    issuance_history: Vec<Balance>,
+    issuance_frozen: bool,
+    interest_earned: HashMap<AccountId, Balance>,
+    fee_collectors: Vec<(AccountId, u32)>,
+    vesting: HashMap<AccountId, VestingSchedule>,
+    pending_reserves: HashMap<AccountId, Balance>,
+    linked_accounts: HashMap<AccountId, Vec<AccountId>>,
+    scheduled: Vec<ScheduledTransfer>,
+    next_schedule_id: ScheduleId,
+    seen_keys: HashSet<IdempotencyKey>,
+    overdrafts: HashMap<AccountId, Balance>,
+    author: Option<AccountId>,
+    block_fees: Balance,
+    pending_fees: BTreeMap<AccountId, Balance>,
+    tags: HashMap<String, BTreeSet<AccountId>>,
+    remainder_dust: Balance,
+    asset_balances: HashMap<(AccountId, AssetId), Balance>,
+    asset_issuance: HashMap<AssetId, Balance>,
+    conversion_rates: HashMap<(AssetId, AssetId), (Balance, Balance)>,
+    asset_existential_deposits: HashMap<AssetId, Balance>,
+}
+
+impl Storage {
+    fn snapshot(&self) -> StorageSnapshot {
+        StorageSnapshot {
+            balances: self.balances.read().unwrap().clone(),
+            reserved: self.reserved.read().unwrap().clone(),
+            named_reserves: self.named_reserves.read().unwrap().clone(),
+            total_issuance: *self.total_issuance.read().unwrap(),
+            block_number: *self.block_number.read().unwrap(),
+            events: self.events.read().unwrap().clone(),
+            next_event_seq: *self.next_event_seq.read().unwrap(),
+            claims: self.claims.read().unwrap().clone(),
+            next_claim_id: *self.next_claim_id.read().unwrap(),
+            creation_order: self.creation_order.read().unwrap().clone(),
+            issuance_history: self.issuance_history.read().unwrap().clone(),
+            issuance_frozen: *self.issuance_frozen.read().unwrap(),
+            interest_earned: self.interest_earned.read().unwrap().clone(),
+            fee_collectors: self.fee_collectors.read().unwrap().clone(),
+            vesting: self.vesting.read().unwrap().clone(),
+            pending_reserves: self.pending_reserves.read().unwrap().clone(),
+            linked_accounts: self.linked_accounts.read().unwrap().clone(),
+            scheduled: self.scheduled.read().unwrap().clone(),
+            next_schedule_id: *self.next_schedule_id.read().unwrap(),
+            seen_keys: self.seen_keys.read().unwrap().clone(),
+            overdrafts: self.overdrafts.read().unwrap().clone(),
+            author: *self.author.read().unwrap(),
+            block_fees: *self.block_fees.read().unwrap(),
+            pending_fees: self.pending_fees.read().unwrap().clone(),
+            tags: self.tags.read().unwrap().clone(),
+            remainder_dust: *self.remainder_dust.read().unwrap(),
+            asset_balances: self.asset_balances.read().unwrap().clone(),
+            asset_issuance: self.asset_issuance.read().unwrap().clone(),
+            conversion_rates: self.conversion_rates.read().unwrap().clone(),
+            asset_existential_deposits: self.asset_existential_deposits.read().unwrap().clone(),
         }
     }
+
+    fn restore(&self, snapshot: StorageSnapshot) {
+        *self.balances.write().unwrap() = snapshot.balances;
+        *self.reserved.write().unwrap() = snapshot.reserved;
+        *self.named_reserves.write().unwrap() = snapshot.named_reserves;
+        *self.total_issuance.write().unwrap() = snapshot.total_issuance;
+        *self.block_number.write().unwrap() = snapshot.block_number;
+        *self.events.write().unwrap() = snapshot.events;
+        *self.next_event_seq.write().unwrap() = snapshot.next_event_seq;
+        *self.claims.write().unwrap() = snapshot.claims;
+        *self.next_claim_id.write().unwrap() = snapshot.next_claim_id;
+        *self.creation_order.write().unwrap() = snapshot.creation_order;
+        *self.issuance_history.write().unwrap() = snapshot.issuance_history;
+        *self.issuance_frozen.write().unwrap() = snapshot.issuance_frozen;
+        *self.interest_earned.write().unwrap() = snapshot.interest_earned;
+        *self.fee_collectors.write().unwrap() = snapshot.fee_collectors;
+        *self.vesting.write().unwrap() = snapshot.vesting;
+        *self.pending_reserves.write().unwrap() = snapshot.pending_reserves;
+        *self.linked_accounts.write().unwrap() = snapshot.linked_accounts;
+        *self.scheduled.write().unwrap() = snapshot.scheduled;
+        *self.next_schedule_id.write().unwrap() = snapshot.next_schedule_id;
+        *self.seen_keys.write().unwrap() = snapshot.seen_keys;
+        *self.overdrafts.write().unwrap() = snapshot.overdrafts;
+        *self.author.write().unwrap() = snapshot.author;
+        *self.block_fees.write().unwrap() = snapshot.block_fees;
+        *self.pending_fees.write().unwrap() = snapshot.pending_fees;
+        *self.tags.write().unwrap() = snapshot.tags;
+        *self.remainder_dust.write().unwrap() = snapshot.remainder_dust;
+        *self.asset_balances.write().unwrap() = snapshot.asset_balances;
+        *self.asset_issuance.write().unwrap() = snapshot.asset_issuance;
+        *self.conversion_rates.write().unwrap() = snapshot.conversion_rates;
+        *self.asset_existential_deposits.write().unwrap() = snapshot.asset_existential_deposits;
+    }
+
+    // Restore a snapshot taken earlier by the current transaction, keeping sequence numbers already handed
+    // out so they are never reused
+    fn rollback(&self, snapshot: StorageSnapshot) {
+        let next_event_seq = *self.next_event_seq.read().unwrap();
+        self.restore(snapshot);
+        *self.next_event_seq.write().unwrap() = next_event_seq;
+    }
 }
 
 impl Default for Storage {
@@ -60,159 +552,3650 @@ impl Default for Storage {
     }
 }
 
+/// Configuration set through the `with_*` builders
+#[derive(Debug, Clone, PartialEq)]
+pub struct PalletConfig {
+    pub max_balance_per_account: Option<Balance>,
+    pub fee: FeeConfig,
+    /// `(until, deployer)`
+    pub launch_lock: Option<(BlockNumber, AccountId)>,
+    pub max_transfer: Option<Balance>,
+    pub remainder_account: Option<AccountId>,
+    /// Basis points per block
+    pub interest_rate: u16,
+    /// Basis points per block
+    pub demurrage_rate: u16,
+    pub block_time_ms: Option<u64>,
+    pub max_event_payload: Option<usize>,
+    pub payload_policy: PayloadPolicy,
+    pub existential_deposit: Balance,
+    pub dust_policy: DustPolicy,
+    /// `(limit, collateral ratio in basis points)`
+    pub overdraft: Option<(Balance, u16)>,
+    /// Treasury receiving each block's burned fees
+    pub fee_sweep: Option<AccountId>,
+}
+
+impl Default for PalletConfig {
+    fn default() -> Self {
+        Self {
+            max_balance_per_account: None,
+            fee: FeeConfig::None,
+            launch_lock: None,
+            max_transfer: None,
+            remainder_account: None,
+            interest_rate: 0,
+            demurrage_rate: 0,
+            block_time_ms: None,
+            max_event_payload: None,
+            payload_policy: PayloadPolicy::Reject,
+            existential_deposit: 0,
+            dust_policy: DustPolicy::Burn,
+            overdraft: None,
+            fee_sweep: None,
+        }
+    }
+}
+
 /// Runtime pallet implementation
 pub struct BalancesPallet {
     storage: Storage,
+    config: PalletConfig,
 }
 
 impl BalancesPallet {
     pub fn new() -> Self {
-        Self {
-            storage: Storage::new(),
+        Self { storage: Storage::new(), config: PalletConfig::default() }
+    }
+
+    /// Cap the free balance any single account may hold
+    pub fn with_max_balance_per_account(mut self, max: Balance) -> Self {
+        self.config.max_balance_per_account = Some(max);
+        self
+    }
+
+    /// Charge transfers according to the given fee configuration
+    pub fn with_fee(mut self, fee: FeeConfig) -> Self {
+        self.config.fee = fee;
+        self
+    }
+
+    /// Restrict operations to calls originating from `deployer` until block `until`
+    pub fn with_launch_lock(mut self, until: BlockNumber, deployer: AccountId) -> Self {
+        self.config.launch_lock = Some((until, deployer));
+        self
+    }
+
+    /// Reject single transfers larger than `max`
+    pub fn with_max_transfer(mut self, max: Balance) -> Self {
+        self.config.max_transfer = Some(max);
+        self
+    }
+
+    /// Collect rounding remainders of integer division in `account` instead of discarding them
+    ///
+    /// A percentage fee with a fractional part is charged rounded up and the extra unit is moved from the
+    /// payer to the account, as are the whole-unit remainders of fee collector splits. Interest is minted,
+    /// so the fractions truncated from it are added up and minted to the account once they reach a whole
+    /// unit. Demurrage and haircuts round the burned amount down, leaving the fraction with its holder, so
+    /// they leave nothing to collect.
+    pub fn with_remainder_account(mut self, account: AccountId) -> Self {
+        self.config.remainder_account = Some(account);
+        self
+    }
+
+    /// Mint interest of `bps` basis points of each free balance at the start of every block
+    pub fn with_interest_rate(mut self, bps: u16) -> Self {
+        self.config.interest_rate = bps;
+        self
+    }
+
+    /// Burn `bps` basis points of each free balance at the start of every block
+    pub fn with_demurrage(mut self, bps: u16) -> Self {
+        self.config.demurrage_rate = bps;
+        self
+    }
+
+    /// Set the wall-clock duration of a block, used for time-based metrics
+    pub fn with_block_time(mut self, millis: u64) -> Self {
+        self.config.block_time_ms = Some(millis);
+        self
+    }
+
+    /// Limit the size of byte payloads such as memos carried by events
+    pub fn with_max_event_payload(mut self, bytes: usize) -> Self {
+        self.config.max_event_payload = Some(bytes);
+        self
+    }
+
+    /// Choose how oversized event payloads are handled, rejecting them by default
+    pub fn with_payload_policy(mut self, policy: PayloadPolicy) -> Self {
+        self.config.payload_policy = policy;
+        self
+    }
+
+    fn limit_payload(&self, mut payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match self.config.max_event_payload {
+            Some(max) if payload.len() > max => match self.config.payload_policy {
+                PayloadPolicy::Reject => Err(Error::InvalidValue),
+                PayloadPolicy::Truncate => {
+                    payload.truncate(max);
+                    Ok(payload)
+                }
+            },
+            _ => Ok(payload),
+        }
+    }
+
+    /// Reap accounts whose free balance drops below `ed` through a withdrawal or outgoing transfer
+    pub fn with_existential_deposit(mut self, ed: Balance) -> Self {
+        self.config.existential_deposit = ed;
+        self
+    }
+
+    /// Choose what happens to the remaining balance of reaped accounts, burning it by default
+    pub fn with_dust_policy(mut self, policy: DustPolicy) -> Self {
+        self.config.dust_policy = policy;
+        self
+    }
+
+    /// Let withdrawals and transfers overdraw an account by up to `limit` in total, provided its reserved
+    /// balance covers `collateral_bps` basis points of the resulting debt
+    pub fn with_overdraft(mut self, limit: Balance, collateral_bps: u16) -> Self {
+        self.config.overdraft = Some((limit, collateral_bps));
+        self
+    }
+
+    /// Credit the fees burned during each block to `treasury` when the block closes, instead of the author
+    pub fn with_fee_sweep(mut self, treasury: AccountId) -> Self {
+        self.config.fee_sweep = Some(treasury);
+        self
+    }
+
+    // Every operation that writes storage holds this for its whole duration
+    fn lock_mutations(&self) -> MutationGuard<'_> {
+        self.storage.mutations.lock()
+    }
+
+    fn ensure_unlocked(&self, origin: AccountId) -> Result<(), Error> {
+        match self.config.launch_lock {
+            Some((until, deployer)) if origin != deployer && self.block_number() < until => Err(Error::LaunchLocked),
+            _ => Ok(()),
+        }
+    }
+
+    /// Forbid operations that change total issuance until `unfreeze_issuance` is called
+    pub fn freeze_issuance(&self) {
+        let _guard = self.lock_mutations();
+        *self.storage.issuance_frozen.write().unwrap() = true;
+    }
+
+    /// Allow operations that change total issuance again
+    pub fn unfreeze_issuance(&self) {
+        let _guard = self.lock_mutations();
+        *self.storage.issuance_frozen.write().unwrap() = false;
+    }
+
+    fn ensure_issuance_unfrozen(&self) -> Result<(), Error> {
+        if *self.storage.issuance_frozen.read().unwrap() {
+            return Err(Error::IssuanceFrozen);
+        }
+        Ok(())
+    }
+
+    // `now` must be read before taking balance locks, as `next_block` holds the block number lock
+    // while reading total issuance
+    fn ensure_liquid(&self, who: AccountId, remaining: Balance, now: BlockNumber) -> Result<(), Error> {
+        match self.storage.vesting.read().unwrap().get(&who) {
+            Some(schedule) if remaining < schedule.locked_at(now) => Err(Error::InsufficientBalance),
+            _ => Ok(()),
+        }
+    }
+
+    fn ensure_within_cap(&self, balance: Balance) -> Result<(), Error> {
+        match self.config.max_balance_per_account {
+            Some(max) if balance > max => Err(Error::BalanceCapExceeded),
+            _ => Ok(()),
         }
     }
 
     /// Deposit tokens to an account
     pub fn deposit(&self, who: AccountId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(who)?;
+        self.ensure_issuance_unfrozen()?;
         let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+        let mut pending_reserves = self.storage.pending_reserves.write().unwrap();
         let mut total = self.storage.total_issuance.write().unwrap();
         
-        let balance = balances.entry(who).or_insert(0);
-        *balance = balance.checked_add(amount).ok_or(Error::Overflow)?;
-        *total = total.checked_add(amount).ok_or(Error::Overflow)?;
+        let to_reserve = pending_reserves.get(&who).copied().unwrap_or(0).min(amount);
+        let new_balance = balances.get(&who).copied().unwrap_or(0).checked_add(amount - to_reserve).ok_or(Error::Overflow)?;
+        self.ensure_within_cap(new_balance)?;
+        let new_total = total.checked_add(amount).ok_or(Error::Overflow)?;
+        add_reserve(&mut reserved, &mut named, who, DEFAULT_RESERVE, to_reserve)?;
+        *total = new_total;
+        self.insert_balance(&mut balances, who, new_balance);
+        if to_reserve > 0 {
+            let pending = pending_reserves.get_mut(&who).unwrap();
+            *pending -= to_reserve;
+            if *pending == 0 {
+                pending_reserves.remove(&who);
+            }
+        }
         
         self.emit_event(Event::Deposit { who, amount });
+        if to_reserve > 0 {
+            self.emit_event(Event::Reserved { who, amount: to_reserve });
+        }
         Ok(())
     }
 
     /// Withdraw tokens from an account
     pub fn withdraw(&self, who: AccountId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(who)?;
+        self.ensure_issuance_unfrozen()?;
+        let now = self.block_number();
         let mut balances = self.storage.balances.write().unwrap();
+        let reserved = self.storage.reserved.read().unwrap();
         let mut total = self.storage.total_issuance.write().unwrap();
         
-        let balance = balances.get_mut(&who).ok_or(Error::AccountNotFound)?;
-        if *balance < amount {
-            return Err(Error::InsufficientBalance);
-        }
+        let balance = balances.get(&who).copied().ok_or(Error::AccountNotFound)?;
+        let shortfall = amount.saturating_sub(balance);
+        let new_debt = self.check_overdraft(&reserved, who, shortfall)?;
+        self.ensure_liquid(who, balance.saturating_sub(amount), now)?;
+        let new_total = total.checked_add(shortfall).ok_or(Error::Overflow)?.checked_sub(amount).ok_or(Error::Underflow)?;
         
-        *balance = balance.checked_sub(amount).ok_or(Error::Underflow)?;
-        *total = total.checked_sub(amount).ok_or(Error::Underflow)?;
+        balances.insert(who, balance + shortfall - amount);
+        *total = new_total;
         
+        self.record_overdraft(who, shortfall, new_debt);
         self.emit_event(Event::Withdraw { who, amount });
+        self.reap_dust_accounts(&mut balances, &reserved, &mut total, who);
         Ok(())
     }
 
     /// Transfer tokens between accounts
     pub fn transfer(&self, from: AccountId, to: AccountId, amount: Balance) -> Result<(), Error> {
+        self.do_transfer(from, to, amount, None)
+    }
+
+    /// Transfer tokens and record a memo alongside the transfer event
+    pub fn transfer_with_memo(&self, from: AccountId, to: AccountId, amount: Balance, memo: Vec<u8>) -> Result<(), Error> {
+        let memo = self.limit_payload(memo)?;
+        self.do_transfer(from, to, amount, Some(memo))
+    }
+
+    fn do_transfer(&self, from: AccountId, to: AccountId, amount: Balance, memo: Option<Vec<u8>>) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(from)?;
+        if self.config.max_transfer.is_some_and(|max| amount > max) {
+            return Err(Error::TransferLimitExceeded);
+        }
+        let (base_fee, mut remainder) = self.split_fee(amount);
+        let fee = base_fee.checked_add(remainder).ok_or(Error::Overflow)?;
+        let (mut shares, split_remainder) = split_by_weight(base_fee, &self.storage.fee_collectors.read().unwrap());
+        let burned = if shares.is_empty() { base_fee } else { 0 };
+        if split_remainder > 0 {
+            match self.config.remainder_account {
+                Some(_) => remainder += split_remainder,
+                None => shares[0].1 += split_remainder,
+            }
+        }
+        if burned > 0 {
+            self.ensure_issuance_unfrozen()?;
+        }
+        let now = self.block_number();
         let mut balances = self.storage.balances.write().unwrap();
+        let reserved = self.storage.reserved.read().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
         
         let from_balance = balances.get(&from).copied().ok_or(Error::AccountNotFound)?;
-        if from_balance < amount {
-            return Err(Error::InsufficientBalance);
+        let debit = amount.checked_add(fee).ok_or(Error::Overflow)?;
+        let shortfall = debit.saturating_sub(from_balance);
+        let new_debt = self.check_overdraft(&reserved, from, shortfall)?;
+        if from != to {
+            self.ensure_liquid(from, from_balance.saturating_sub(debit), now)?;
         }
+        let new_total = total.checked_add(shortfall).ok_or(Error::Overflow)?.checked_sub(burned).ok_or(Error::Underflow)?;
+        let new_from_balance = from_balance.saturating_add(shortfall).checked_sub(debit).ok_or(Error::Underflow)?;
         
-        let to_balance = balances.entry(to).or_insert(0);
-        *to_balance = to_balance.checked_add(amount).ok_or(Error::Overflow)?;
+        shares.retain(|(_, share)| *share > 0);
+        let mut credits = vec![(to, amount)];
+        credits.extend(shares.iter().copied());
+        credits.extend(self.config.remainder_account.filter(|_| remainder > 0).map(|account| (account, remainder)));
+        // Resulting balance of every credited account, in the order they are first credited so that
+        // account creation order is kept
+        let mut credited: Vec<(AccountId, Balance)> = Vec::with_capacity(credits.len());
+        for (who, credit) in credits {
+            match credited.iter_mut().find(|(account, _)| *account == who) {
+                Some((_, balance)) => *balance = balance.checked_add(credit).ok_or(Error::Overflow)?,
+                None => {
+                    let balance = if who == from { new_from_balance } else { balances.get(&who).copied().unwrap_or(0) };
+                    credited.push((who, balance.checked_add(credit).ok_or(Error::Overflow)?));
+                }
+            }
+        }
+        for (who, balance) in &credited {
+            if *balance > balances.get(who).copied().unwrap_or(0) {
+                self.ensure_within_cap(*balance)?;
+            }
+        }
         
-        let from_balance = balances.get_mut(&from).unwrap();
-        *from_balance = from_balance.checked_sub(amount).ok_or(Error::Underflow)?;
+        balances.insert(from, new_from_balance);
+        for (who, balance) in credited {
+            self.insert_balance(&mut balances, who, balance);
+        }
+        *total = new_total;
+        if burned > 0 {
+            let mut block_fees = self.storage.block_fees.write().unwrap();
+            *block_fees = block_fees.saturating_add(burned);
+        }
+        let remainder_account = self.config.remainder_account.filter(|_| remainder > 0);
         
+        self.record_overdraft(from, shortfall, new_debt);
         self.emit_event(Event::Transfer { from, to, amount });
+        if let Some(memo) = memo {
+            self.emit_event(Event::Memo { from, to, memo });
+        }
+        if fee > 0 {
+            self.emit_event(Event::FeeCharged { who: from, amount: fee });
+        }
+        for (collector, share) in shares {
+            self.emit_event(Event::FeeCollected { who: collector, amount: share });
+        }
+        if let Some(account) = remainder_account {
+            self.emit_event(Event::RemainderCollected { who: account, amount: remainder });
+        }
+        if from != to {
+            self.reap_dust_accounts(&mut balances, &reserved, &mut total, from);
+        }
         Ok(())
     }
 
-    /// Get balance of an account
-    pub fn balance_of(&self, who: AccountId) -> Balance {
-        self.storage.balances.read().unwrap().get(&who).copied().unwrap_or(0)
+    // Add `dust` ten-thousandths of a unit of truncated interest to the pending remainder and mint whatever
+    // whole units it has reached to the remainder account, returning the minted amount. Units stay pending
+    // while issuance is frozen or the account is at its cap.
+    fn collect_dust(&self, balances: &mut HashMap<AccountId, Balance>, total: &mut Balance, dust: Balance) -> Balance {
+        let account = match self.config.remainder_account {
+            Some(account) => account,
+            None => return 0,
+        };
+        let mut pending = self.storage.remainder_dust.write().unwrap();
+        *pending = pending.saturating_add(dust);
+        let units = *pending / 10_000;
+        if units == 0 || self.ensure_issuance_unfrozen().is_err() {
+            return 0;
+        }
+        let collected = match balances.get(&account).copied().unwrap_or(0).checked_add(units) {
+            Some(collected) if self.ensure_within_cap(collected).is_ok() => collected,
+            _ => return 0,
+        };
+        let new_total = match total.checked_add(units) {
+            Some(new_total) => new_total,
+            None => return 0,
+        };
+        self.insert_balance(balances, account, collected);
+        *total = new_total;
+        *pending -= units * 10_000;
+        units
     }
 
-    /// Get total issuance
-    pub fn total_issuance(&self) -> Balance {
-        *self.storage.total_issuance.read().unwrap()
+    // Debt `who` would owe after overdrawing by `shortfall`, or `None` if no overdraft is needed
+    fn check_overdraft(
+        &self,
+        reserved: &HashMap<AccountId, Balance>,
+        who: AccountId,
+        shortfall: Balance,
+    ) -> Result<Option<Balance>, Error> {
+        if shortfall == 0 {
+            return Ok(None);
+        }
+        let (limit, collateral_bps) = self.config.overdraft.ok_or(Error::InsufficientBalance)?;
+        // The overdrawn amount is credited before being spent, which mints it
+        self.ensure_issuance_unfrozen()?;
+        let debt = self.storage.overdrafts.read().unwrap().get(&who).copied().unwrap_or(0);
+        let new_debt = debt.checked_add(shortfall).ok_or(Error::Overflow)?;
+        if new_debt > limit || reserved.get(&who).copied().unwrap_or(0) < collateral_for(new_debt, collateral_bps) {
+            return Err(Error::InsufficientBalance);
+        }
+        Ok(Some(new_debt))
     }
 
-    /// Advance to next block
-    pub fn next_block(&self) {
-        let mut block_number = self.storage.block_number.write().unwrap();
-        *block_number += 1;
-        self.emit_event(Event::NewBlock { number: *block_number });
+    fn record_overdraft(&self, who: AccountId, shortfall: Balance, new_debt: Option<Balance>) {
+        if let Some(debt) = new_debt {
+            self.storage.overdrafts.write().unwrap().insert(who, debt);
+            self.emit_event(Event::Overdrawn { who, amount: shortfall });
+        }
     }
 
-    /// Get current block number
-    pub fn block_number(&self) -> BlockNumber {
-        *self.storage.block_number.read().unwrap()
+    /// Outstanding overdraft debt of an account
+    pub fn overdraft_of(&self, who: AccountId) -> Balance {
+        self.storage.overdrafts.read().unwrap().get(&who).copied().unwrap_or(0)
     }
 
-    fn emit_event(&self, event: Event) {
-        self.storage.events.write().unwrap().push(event);
+    /// Reserved balance needed to back the account's current overdraft debt at the configured ratio
+    pub fn required_collateral(&self, who: AccountId) -> Balance {
+        let collateral_bps = self.config.overdraft.map_or(0, |(_, collateral_bps)| collateral_bps);
+        collateral_for(self.overdraft_of(who), collateral_bps)
     }
 
-    /// Get all events
-    pub fn events(&self) -> Vec<Event> {
-        self.storage.events.read().unwrap().clone()
+    /// Pay down overdraft debt from free balance, burning the repaid amount
+    pub fn repay_overdraft(&self, who: AccountId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_issuance_unfrozen()?;
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+        let mut overdrafts = self.storage.overdrafts.write().unwrap();
+
+        let debt = overdrafts.get(&who).copied().unwrap_or(0);
+        let balance = balances.get_mut(&who).ok_or(Error::AccountNotFound)?;
+        if amount > debt {
+            return Err(Error::InvalidValue);
+        }
+        if *balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        *balance -= amount;
+        *total = total.saturating_sub(amount);
+        if amount == debt {
+            overdrafts.remove(&who);
+        } else {
+            overdrafts.insert(who, debt - amount);
+        }
+
+        self.emit_event(Event::OverdraftRepaid { who, amount });
+        Ok(())
     }
-}
 
-impl Default for BalancesPallet {
-    fn default() -> Self {
-        Self::new()
+    /// Link `child` to `parent` so the child is reaped along with the parent when it is also below the
+    /// existential deposit
+    pub fn link_accounts(&self, parent: AccountId, child: AccountId) {
+        let _guard = self.lock_mutations();
+        let mut links = self.storage.linked_accounts.write().unwrap();
+        let children = links.entry(parent).or_default();
+        if !children.contains(&child) {
+            children.push(child);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Add an account to the group named `tag`
+    pub fn tag_account(&self, who: AccountId, tag: String) {
+        let _guard = self.lock_mutations();
+        self.storage.tags.write().unwrap().entry(tag).or_default().insert(who);
+    }
 
-    #[test]
-    fn test_deposit() {
-        let pallet = BalancesPallet::new();
-        pallet.deposit(1, 1000).unwrap();
-        assert_eq!(pallet.balance_of(1), 1000);
-        assert_eq!(pallet.total_issuance(), 1000);
+    /// Accounts carrying `tag`, in id order
+    pub fn accounts_with_tag(&self, tag: &str) -> Vec<AccountId> {
+        let tags = self.storage.tags.read().unwrap();
+        tags.get(tag).into_iter().flatten().copied().collect()
     }
 
-    #[test]
-    fn test_withdraw() {
-        let pallet = BalancesPallet::new();
-        pallet.deposit(1, 1000).unwrap();
-        pallet.withdraw(1, 500).unwrap();
-        assert_eq!(pallet.balance_of(1), 500);
-        assert_eq!(pallet.total_issuance(), 500);
+    /// Deposit `amount_each` to every account carrying `tag`, all or nothing
+    pub fn airdrop_to_tag(&self, tag: &str, amount_each: Balance) -> Result<(), Error> {
+        let recipients = self.accounts_with_tag(tag);
+        self.transactional(|| recipients.into_iter().try_for_each(|who| self.deposit(who, amount_each)))
     }
 
-    #[test]
-    fn test_withdraw_insufficient() {
-        let pallet = BalancesPallet::new();
-        pallet.deposit(1, 100).unwrap();
-        assert_eq!(pallet.withdraw(1, 200), Err(Error::InsufficientBalance));
+    /// Whether the account currently exists in storage
+    pub fn account_exists(&self, who: AccountId) -> bool {
+        self.storage.balances.read().unwrap().contains_key(&who)
     }
 
-    #[test]
-    fn test_transfer() {
-        let pallet = BalancesPallet::new();
-        pallet.deposit(1, 1000).unwrap();
-        pallet.transfer(1, 2, 300).unwrap();
-        assert_eq!(pallet.balance_of(1), 700);
-        assert_eq!(pallet.balance_of(2), 300);
+    // Reap `who` if its free balance is below the existential deposit and it has nothing reserved and no
+    // overdraft debt, cascading to linked children that are also below it
+    fn reap_dust_accounts(
+        &self,
+        balances: &mut HashMap<AccountId, Balance>,
+        reserved: &HashMap<AccountId, Balance>,
+        total: &mut Balance,
+        who: AccountId,
+    ) {
+        let ed = self.config.existential_deposit;
+        // Burning dust would change issuance
+        if ed == 0 || (self.config.dust_policy == DustPolicy::Burn && self.ensure_issuance_unfrozen().is_err()) {
+            return;
+        }
+        let overdrafts = self.storage.overdrafts.read().unwrap();
+        let links = self.storage.linked_accounts.read().unwrap();
+
+        let mut queue = vec![who];
+        while let Some(account) = queue.pop() {
+            let free = match balances.get(&account) {
+                Some(free)
+                    if *free < ed
+                        && reserved.get(&account).copied().unwrap_or(0) == 0
+                        && !overdrafts.contains_key(&account) =>
+                {
+                    *free
+                }
+                _ => continue,
+            };
+            // Dust the destination can't take without breaking its cap is burned instead
+            let collected = match self.config.dust_policy {
+                DustPolicy::Transfer(destination) if destination != account => balances
+                    .get(&destination)
+                    .copied()
+                    .unwrap_or(0)
+                    .checked_add(free)
+                    .filter(|collected| self.ensure_within_cap(*collected).is_ok())
+                    .map(|collected| (destination, collected)),
+                _ => None,
+            };
+            match collected {
+                Some((destination, collected)) => {
+                    if free > 0 {
+                        self.insert_balance(balances, destination, collected);
+                        self.emit_event(Event::DustCollected { from: account, to: destination, amount: free });
+                    }
+                }
+                None => {
+                    // A dust account cannot collect its own dust either, and burning changes issuance
+                    if free > 0 && self.ensure_issuance_unfrozen().is_err() {
+                        continue;
+                    }
+                    *total = total.saturating_sub(free);
+                    if free > 0 {
+                        self.emit_event(Event::DustLost { who: account, amount: free });
+                    }
+                }
+            }
+            balances.remove(&account);
+            self.storage.creation_order.write().unwrap().retain(|existing| *existing != account);
+            self.storage.vesting.write().unwrap().remove(&account);
+            self.emit_event(Event::AccountReaped { who: account });
+            queue.extend(links.get(&account).into_iter().flatten().copied());
+        }
     }
 
-    #[test]
-    fn test_events() {
-        let pallet = BalancesPallet::new();
-        pallet.deposit(1, 100).unwrap();
-        pallet.transfer(1, 2, 50).unwrap();
-        
-        let events = pallet.events();
-        assert_eq!(events.len(), 2);
-        assert_eq!(events[0], Event::Deposit { who: 1, amount: 100 });
-        assert_eq!(events[1], Event::Transfer { from: 1, to: 2, amount: 50 });
+    /// Credit the fees burned so far in the current block to the outgoing author and make `new_author`
+    /// the author, returning the amount settled to the outgoing author
+    ///
+    /// With a fee sweep configured the fees belong to the treasury, so this only swaps the author and
+    /// returns 0.
+    pub fn rotate_author(&self, new_author: AccountId) -> Balance {
+        let _guard = self.lock_mutations();
+        let mut author = self.storage.author.write().unwrap();
+        let settled = match self.config.fee_sweep {
+            Some(_) => 0,
+            None => self.settle_block_fees(*author, |who, amount| Event::AuthorSettled { who, amount }),
+        };
+        *author = Some(new_author);
+        settled
     }
 
-    #[test]
-    fn test_block_number() {
-        let pallet = BalancesPallet::new();
-        assert_eq!(pallet.block_number(), 0);
-        pallet.next_block();
-        assert_eq!(pallet.block_number(), 1);
+    /// Author credited with the fees burned in the current block, unless a fee sweep is configured
+    pub fn author(&self) -> Option<AccountId> {
+        *self.storage.author.read().unwrap()
+    }
+
+    // Reset the block's fee counter, owing the counted fees to `recipient`, and mint every owed amount that
+    // can be credited, returning the amount credited to `recipient`. Without a recipient the fees stay
+    // burned. Fees stay owed to the recipient that earned them while issuance is frozen or the credit would
+    // break its balance cap, and are settled by a later call.
+    fn settle_block_fees(&self, recipient: Option<AccountId>, event: impl Fn(AccountId, Balance) -> Event) -> Balance {
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+        let mut block_fees = self.storage.block_fees.write().unwrap();
+        let mut pending_fees = self.storage.pending_fees.write().unwrap();
+
+        let amount = std::mem::take(&mut *block_fees);
+        if let Some(who) = recipient.filter(|_| amount > 0) {
+            let owed = pending_fees.entry(who).or_insert(0);
+            *owed = owed.saturating_add(amount);
+        }
+        if self.ensure_issuance_unfrozen().is_err() {
+            return 0;
+        }
+        let mut settled = 0;
+        for (who, owed) in pending_fees.iter_mut() {
+            let credited = match balances.get(who).copied().unwrap_or(0).checked_add(*owed) {
+                Some(credited) if self.ensure_within_cap(credited).is_ok() => credited,
+                _ => continue,
+            };
+            let new_total = match total.checked_add(*owed) {
+                Some(new_total) => new_total,
+                None => continue,
+            };
+            self.insert_balance(&mut balances, *who, credited);
+            *total = new_total;
+            self.emit_event(event(*who, *owed));
+            if Some(*who) == recipient {
+                settled = *owed;
+            }
+            *owed = 0;
+        }
+        pending_fees.retain(|_, owed| *owed > 0);
+        settled
+    }
+
+    /// Settle `(debtor, creditor, amount)` obligations by applying only each account's net position, all or
+    /// nothing, and return the non-zero positions in account order
+    pub fn net_settle(&self, obligations: &[(AccountId, AccountId, Balance)]) -> Result<Vec<(AccountId, i128)>, Error> {
+        let _guard = self.lock_mutations();
+        let mut positions: BTreeMap<AccountId, i128> = BTreeMap::new();
+        for (debtor, creditor, amount) in obligations {
+            let amount = i128::try_from(*amount).map_err(|_| Error::Overflow)?;
+            for (who, delta) in [(*debtor, -amount), (*creditor, amount)] {
+                let position = positions.entry(who).or_insert(0);
+                *position = position.checked_add(delta).ok_or(Error::Overflow)?;
+            }
+        }
+        positions.retain(|_, delta| *delta != 0);
+        for (&who, _) in positions.iter().filter(|(_, delta)| **delta < 0) {
+            self.ensure_unlocked(who)?;
+        }
+
+        let now = self.block_number();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut settled = Vec::with_capacity(positions.len());
+        for (&who, &delta) in &positions {
+            let balance = balances.get(&who).copied();
+            let new_balance = if delta < 0 {
+                let balance = balance.ok_or(Error::AccountNotFound)?;
+                let new_balance = balance.checked_sub(delta.unsigned_abs()).ok_or(Error::InsufficientBalance)?;
+                self.ensure_liquid(who, new_balance, now)?;
+                new_balance
+            } else {
+                let new_balance = balance.unwrap_or(0).checked_add(delta.unsigned_abs()).ok_or(Error::Overflow)?;
+                self.ensure_within_cap(new_balance)?;
+                new_balance
+            };
+            settled.push((who, new_balance));
+        }
+
+        for (who, new_balance) in settled {
+            self.insert_balance(&mut balances, who, new_balance);
+            self.emit_event(Event::NetSettled { who, delta: positions[&who] });
+        }
+        Ok(positions.into_iter().collect())
+    }
+
+    /// Transfer tokens in as many legs as needed to respect the transfer limit, returning the leg count
+    pub fn transfer_auto_split(&self, from: AccountId, to: AccountId, amount: Balance) -> Result<usize, Error> {
+        let leg_size = match self.config.max_transfer {
+            Some(max) if amount > max => max,
+            _ => return self.transfer(from, to, amount).map(|_| 1),
+        };
+        if leg_size == 0 {
+            return Err(Error::TransferLimitExceeded);
+        }
+
+        self.transactional(|| {
+            let mut remaining = amount;
+            let mut legs = 0;
+            while remaining > 0 {
+                let leg = remaining.min(leg_size);
+                self.transfer(from, to, leg)?;
+                remaining -= leg;
+                legs += 1;
+            }
+            Ok(legs)
+        })
+    }
+
+    /// Settle a sale by paying `price` to the seller and `fee` to the fee account, all or nothing
+    pub fn escrow_transfer(
+        &self,
+        buyer: AccountId,
+        seller: AccountId,
+        fee_account: AccountId,
+        price: Balance,
+        fee: Balance,
+    ) -> Result<(), Error> {
+        let total = price.checked_add(fee).ok_or(Error::Overflow)?;
+        if self.balance_of(buyer) < total {
+            return Err(Error::InsufficientBalance);
+        }
+
+        self.transactional(|| {
+            self.transfer(buyer, seller, price)?;
+            self.transfer(buyer, fee_account, fee)
+        })
+    }
+
+    /// Distribute charged fees among collectors in proportion to their weights instead of burning them
+    ///
+    /// The rounding remainder goes to the remainder account if configured, otherwise to the heaviest
+    /// collector. An empty list restores burning.
+    pub fn set_fee_collectors(&self, collectors: &[(AccountId, u32)]) {
+        let _guard = self.lock_mutations();
+        *self.storage.fee_collectors.write().unwrap() = collectors.to_vec();
+    }
+
+    fn transfer_fee(&self, amount: Balance) -> Balance {
+        let (base_fee, remainder) = self.split_fee(amount);
+        base_fee.saturating_add(remainder)
+    }
+
+    // Split a transfer's fee into the part that is burned or split between collectors and the rounding
+    // remainder owed to the remainder account, if one is configured
+    fn split_fee(&self, amount: Balance) -> (Balance, Balance) {
+        match &self.config.fee {
+            FeeConfig::None => (0, 0),
+            FeeConfig::Flat(fee) => (*fee, 0),
+            FeeConfig::Percentage(bps) => {
+                let remainder = match self.config.remainder_account {
+                    Some(_) if bps_fraction(amount, *bps) > 0 => 1,
+                    _ => 0,
+                };
+                (bps_of(amount, *bps), remainder)
+            }
+            FeeConfig::Tiered(tiers) => {
+                let fee = tiers
+                    .iter()
+                    .filter(|(threshold, _)| *threshold <= amount)
+                    .max_by_key(|(threshold, _)| *threshold)
+                    .map(|(_, fee)| *fee)
+                    .unwrap_or(0);
+                (fee, 0)
+            }
+        }
+    }
+
+    /// Fee a call would be charged under the current fee configuration, without executing it
+    pub fn estimate_fee(&self, call: &Call) -> Balance {
+        match call {
+            Call::Transfer { amount, .. } => self.transfer_fee(*amount),
+            Call::Deposit { .. } | Call::Withdraw { .. } => 0,
+        }
+    }
+
+    /// Dispatch a call on behalf of `origin`
+    pub fn dispatch(&self, origin: AccountId, call: Call) -> Result<(), Error> {
+        match call {
+            Call::Transfer { to, amount } => self.transfer(origin, to, amount),
+            Call::Deposit { amount } => self.deposit(origin, amount),
+            Call::Withdraw { amount } => self.withdraw(origin, amount),
+        }
+    }
+
+    /// Dispatch a call at most once per key, failing with `Error::DuplicateOperation` if the key was
+    /// already used by a successful call
+    pub fn dispatch_idempotent(&self, key: IdempotencyKey, origin: AccountId, call: Call) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        // Held across the dispatch so concurrent calls with the same key cannot both apply
+        let mut seen_keys = self.storage.seen_keys.write().unwrap();
+        if seen_keys.contains(&key) {
+            return Err(Error::DuplicateOperation);
+        }
+        self.dispatch(origin, call)?;
+        seen_keys.insert(key);
+        Ok(())
+    }
+
+    /// Transfer tokens that the recipient must claim before `expires_at`, reserving them from the sender meanwhile
+    pub fn transfer_claimable(
+        &self,
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+        expires_at: BlockNumber,
+    ) -> Result<ClaimId, Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(from)?;
+        let now = self.block_number();
+        if expires_at <= now {
+            return Err(Error::InvalidValue);
+        }
+
+        let mut claims = self.storage.claims.write().unwrap();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+
+        let from_balance = balances.get_mut(&from).ok_or(Error::AccountNotFound)?;
+        if *from_balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        self.ensure_liquid(from, *from_balance - amount, now)?;
+        add_reserve(&mut reserved, &mut named, from, CLAIM_RESERVE, amount)?;
+        *from_balance -= amount;
+
+        let mut next_id = self.storage.next_claim_id.write().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        claims.insert(id, PendingClaim { from, to, amount, expires_at });
+        self.emit_event(Event::Reserved { who: from, amount });
+        Ok(id)
+    }
+
+    /// Complete a claimable transfer, crediting the reserved funds to the recipient
+    pub fn claim(&self, id: ClaimId, to: AccountId) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(to)?;
+        let block_number = self.block_number();
+        let mut claims = self.storage.claims.write().unwrap();
+        let pending = claims.get(&id).ok_or(Error::InvalidValue)?;
+        if pending.to != to || pending.expires_at <= block_number {
+            return Err(Error::InvalidValue);
+        }
+
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+
+        let new_to_balance = balances.get(&to).copied().unwrap_or(0).checked_add(pending.amount).ok_or(Error::Overflow)?;
+        self.ensure_within_cap(new_to_balance)?;
+        remove_reserve(&mut reserved, &mut named, pending.from, CLAIM_RESERVE, pending.amount)?;
+        self.insert_balance(&mut balances, to, new_to_balance);
+
+        let PendingClaim { from, amount, .. } = claims.remove(&id).unwrap();
+        self.emit_event(Event::Unreserved { who: from, amount });
+        self.emit_event(Event::Transfer { from, to, amount });
+        Ok(())
+    }
+
+    fn refund_expired_claims(&self, block_number: BlockNumber) {
+        let mut claims = self.storage.claims.write().unwrap();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+
+        let mut expired: Vec<ClaimId> = claims
+            .iter()
+            .filter(|(_, pending)| pending.expires_at <= block_number)
+            .map(|(id, _)| *id)
+            .collect();
+        expired.sort_unstable();
+
+        for id in expired {
+            let PendingClaim { from, amount, .. } = claims[&id];
+            // A claim whose reserve has gone missing is left in place for `audit_reserves` to surface
+            if remove_reserve(&mut reserved, &mut named, from, CLAIM_RESERVE, amount).is_err() {
+                continue;
+            }
+            claims.remove(&id);
+            let from_balance = balances.get(&from).copied().unwrap_or(0).saturating_add(amount);
+            self.insert_balance(&mut balances, from, from_balance);
+            self.emit_event(Event::ClaimRefunded { id, who: from, amount });
+        }
+    }
+
+    /// Run `f`, restoring storage to its prior state if it fails
+    fn transactional<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let _guard = self.lock_mutations();
+        let snapshot = self.storage.snapshot();
+        let result = f();
+        if result.is_err() {
+            self.storage.rollback(snapshot);
+        }
+        result
+    }
+
+    /// Run `f`, rolling back its effects with `Error::IssuanceMismatch` unless total issuance changed by
+    /// exactly `expected_issuance_delta`
+    pub fn with_conservation_check<T>(&self, expected_issuance_delta: i128, f: impl FnOnce(&Self) -> T) -> Result<T, Error> {
+        let _guard = self.lock_mutations();
+        let snapshot = self.storage.snapshot();
+        let before = snapshot.total_issuance;
+        let result = f(self);
+        if to_signed(self.total_issuance()) - to_signed(before) != expected_issuance_delta {
+            self.storage.rollback(snapshot);
+            return Err(Error::IssuanceMismatch);
+        }
+        Ok(result)
+    }
+
+    // Independent copy of the pallet's configuration and state
+    fn fork(&self) -> BalancesPallet {
+        let _guard = self.lock_mutations();
+        let storage = Storage::new();
+        storage.restore(self.storage.snapshot());
+        BalancesPallet { storage, config: self.config.clone() }
+    }
+
+    /// Apply a call to a throwaway fork and return the events it would emit
+    pub fn simulate_call(&self, origin: AccountId, call: Call) -> Result<Vec<Event>, Error> {
+        let fork = self.fork();
+        let emitted_before = fork.storage.events.read().unwrap().len();
+        fork.dispatch(origin, call)?;
+        let events = fork.events().split_off(emitted_before);
+        Ok(events)
+    }
+
+    /// Apply all calls, then commit only if `verify` holds for the resulting state
+    pub fn atomic_batch(&self, calls: &[(AccountId, Call)], verify: impl Fn(&BalancesPallet) -> bool) -> Result<(), Error> {
+        self.transactional(|| {
+            for (origin, call) in calls {
+                self.dispatch(*origin, call.clone())?;
+            }
+            if verify(self) {
+                Ok(())
+            } else {
+                Err(Error::PostconditionFailed)
+            }
+        })
+    }
+
+    // Every write that may create an account goes through here so creation order stays in step
+    fn insert_balance(&self, balances: &mut HashMap<AccountId, Balance>, who: AccountId, value: Balance) {
+        if balances.insert(who, value).is_none() {
+            self.storage.creation_order.write().unwrap().push(who);
+        }
+    }
+
+    // Drop accounts removed by a bulk import and append newly imported ones in id order
+    fn reconcile_creation_order(&self, balances: &HashMap<AccountId, Balance>) {
+        let mut order = self.storage.creation_order.write().unwrap();
+        order.retain(|who| balances.contains_key(who));
+        let known: HashSet<AccountId> = order.iter().copied().collect();
+        let mut added: Vec<AccountId> = balances.keys().filter(|who| !known.contains(who)).copied().collect();
+        added.sort_unstable();
+        order.extend(added);
+    }
+
+    /// Accounts in the order they were first funded
+    pub fn accounts_by_creation(&self) -> Vec<AccountId> {
+        self.storage.creation_order.read().unwrap().clone()
+    }
+
+    /// Dispatch calls from `from` in order with the given failure semantics
+    pub fn batch(&self, from: AccountId, calls: &[Call], atomicity: Atomicity) -> BatchResult {
+        let _guard = self.lock_mutations();
+        let snapshot = match atomicity {
+            Atomicity::AllOrNothing => Some(self.storage.snapshot()),
+            Atomicity::BestEffort | Atomicity::StopOnError => None,
+        };
+
+        let mut outcomes = Vec::with_capacity(calls.len());
+        for call in calls {
+            match self.dispatch(from, call.clone()) {
+                Ok(()) => outcomes.push(CallOutcome::Applied),
+                Err(error) => {
+                    outcomes.push(CallOutcome::Failed(error));
+                    if atomicity == Atomicity::BestEffort {
+                        continue;
+                    }
+                    if let Some(snapshot) = snapshot {
+                        self.storage.rollback(snapshot);
+                        for outcome in outcomes.iter_mut().filter(|outcome| **outcome == CallOutcome::Applied) {
+                            *outcome = CallOutcome::RolledBack;
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        outcomes.resize(calls.len(), CallOutcome::Skipped);
+        BatchResult { outcomes }
+    }
+
+    /// Get balance of an account
+    pub fn balance_of(&self, who: AccountId) -> Balance {
+        self.storage.balances.read().unwrap().get(&who).copied().unwrap_or(0)
+    }
+
+    /// Free balance once pending claims to the account are claimed and scheduled transfers involving it,
+    /// including their fees, have run
+    pub fn projected_balance(&self, who: AccountId) -> Balance {
+        let claims = self.storage.claims.read().unwrap();
+        let balances = self.storage.balances.read().unwrap();
+        let scheduled = self.storage.scheduled.read().unwrap();
+
+        let incoming_claims = claims.values().filter(|pending| pending.to == who).map(|pending| pending.amount);
+        let incoming_scheduled = scheduled.iter().filter(|item| item.to == who).map(|item| item.amount);
+        let credits = incoming_claims.chain(incoming_scheduled).fold(0, |sum: Balance, amount| sum.saturating_add(amount));
+        let debits = scheduled
+            .iter()
+            .filter(|item| item.from == who)
+            .fold(0, |sum: Balance, item| sum.saturating_add(item.amount).saturating_add(self.transfer_fee(item.amount)));
+        balances.get(&who).copied().unwrap_or(0).saturating_add(credits).saturating_sub(debits)
+    }
+
+    /// Get reserved balance of an account
+    pub fn reserved_balance_of(&self, who: AccountId) -> Balance {
+        self.storage.reserved.read().unwrap().get(&who).copied().unwrap_or(0)
+    }
+
+    /// Lock part of an account's free balance under a linear vesting schedule
+    pub fn add_vesting_schedule(&self, who: AccountId, schedule: VestingSchedule) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        let now = self.block_number();
+        let balances = self.storage.balances.read().unwrap();
+        let mut vesting = self.storage.vesting.write().unwrap();
+
+        if schedule.per_block == 0 || vesting.get(&who).is_some_and(|existing| existing.locked_at(now) > 0) {
+            return Err(Error::InvalidValue);
+        }
+        if balances.get(&who).copied().unwrap_or(0) < schedule.locked_at(now) {
+            return Err(Error::InsufficientBalance);
+        }
+        vesting.insert(who, schedule);
+        Ok(())
+    }
+
+    /// Free balance still locked by vesting
+    pub fn locked_balance_of(&self, who: AccountId) -> Balance {
+        let now = self.block_number();
+        self.storage.vesting.read().unwrap().get(&who).map_or(0, |schedule| schedule.locked_at(now))
+    }
+
+    /// Current configuration
+    pub fn config(&self) -> PalletConfig {
+        self.config.clone()
+    }
+
+    /// Accounts with an active vesting schedule and their currently locked amount, sorted by account
+    pub fn vesting_accounts(&self) -> Vec<(AccountId, Balance)> {
+        let now = self.block_number();
+        let mut accounts: Vec<(AccountId, Balance)> = self
+            .storage
+            .vesting
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(who, schedule)| (*who, schedule.locked_at(now)))
+            .filter(|(_, locked)| *locked > 0)
+            .collect();
+        accounts.sort_unstable();
+        accounts
+    }
+
+    /// Free balance not locked by vesting
+    pub fn spendable_balance_of(&self, who: AccountId) -> Balance {
+        self.balance_of(who).saturating_sub(self.locked_balance_of(who))
+    }
+
+    /// Project the spendable balance at a future block from vesting unlocks and per-block interest and
+    /// demurrage, ignoring scheduled transfers and claims
+    ///
+    /// Interest and demurrage round every block, so the projection steps through the blocks one at a time
+    /// and stops early once a block leaves the balance unchanged. That costs time linear in the distance,
+    /// which is why this returns a `Result` rather than a bare `Balance`: blocks more than
+    /// `MAX_PROJECTION_BLOCKS` ahead fail with `Error::InvalidValue` instead of stalling the caller.
+    pub fn spendable_balance_at(&self, who: AccountId, block: BlockNumber) -> Result<Balance, Error> {
+        let now = self.block_number();
+        if block.saturating_sub(now) > MAX_PROJECTION_BLOCKS {
+            return Err(Error::InvalidValue);
+        }
+        let locked = self.storage.vesting.read().unwrap().get(&who).map_or(0, |schedule| schedule.locked_at(block));
+
+        let mut balance = self.balance_of(who);
+        if self.ensure_issuance_unfrozen().is_ok() {
+            for _ in now..block {
+                let mut interest = bps_of(balance, self.config.interest_rate);
+                if let Some(max) = self.config.max_balance_per_account {
+                    interest = interest.min(max.saturating_sub(balance));
+                }
+                let accrued = balance.saturating_add(interest);
+                let next = accrued - bps_of(accrued, self.config.demurrage_rate);
+                // Every later block would leave the balance unchanged as well
+                if next == balance {
+                    break;
+                }
+                balance = next;
+            }
+        }
+        Ok(balance.saturating_sub(locked))
+    }
+
+    /// Commit an account to reserving up to `amount` out of its next deposits before crediting free balance
+    pub fn reserve_on_deposit(&self, who: AccountId, amount: Balance) {
+        let _guard = self.lock_mutations();
+        let mut pending_reserves = self.storage.pending_reserves.write().unwrap();
+        let pending = pending_reserves.entry(who).or_insert(0);
+        *pending = pending.saturating_add(amount);
+    }
+
+    /// Amount still waiting to be reserved from future deposits
+    pub fn pending_reserve_of(&self, who: AccountId) -> Balance {
+        self.storage.pending_reserves.read().unwrap().get(&who).copied().unwrap_or(0)
+    }
+
+    /// Move tokens from an account's free balance into its reserved balance
+    pub fn reserve(&self, who: AccountId, amount: Balance) -> Result<(), Error> {
+        self.reserve_named(DEFAULT_RESERVE, who, amount)
+    }
+
+    /// Move tokens from an account's reserved balance back into its free balance
+    pub fn unreserve(&self, who: AccountId, amount: Balance) -> Result<(), Error> {
+        self.unreserve_named(DEFAULT_RESERVE, who, amount)
+    }
+
+    /// Move tokens from an account's free balance into the named reserve
+    pub fn reserve_named(&self, id: ReserveId, who: AccountId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(who)?;
+        let now = self.block_number();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+
+        let balance = balances.get_mut(&who).ok_or(Error::AccountNotFound)?;
+        if *balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        self.ensure_liquid(who, *balance - amount, now)?;
+        add_reserve(&mut reserved, &mut named, who, id, amount)?;
+        *balance -= amount;
+
+        self.emit_event(Event::Reserved { who, amount });
+        Ok(())
+    }
+
+    /// Move tokens from the named reserve back into an account's free balance
+    pub fn unreserve_named(&self, id: ReserveId, who: AccountId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(who)?;
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+
+        let new_balance = balances.get(&who).copied().unwrap_or(0).checked_add(amount).ok_or(Error::Overflow)?;
+        self.ensure_within_cap(new_balance)?;
+        remove_reserve(&mut reserved, &mut named, who, id, amount)?;
+        self.insert_balance(&mut balances, who, new_balance);
+
+        self.emit_event(Event::Unreserved { who, amount });
+        Ok(())
+    }
+
+    /// Get the balance held in a named reserve
+    pub fn named_reserve_of(&self, id: ReserveId, who: AccountId) -> Balance {
+        let named = self.storage.named_reserves.read().unwrap();
+        named.get(&who).and_then(|reserves| reserves.get(&id)).copied().unwrap_or(0)
+    }
+
+    /// Accounts whose reserved balance differs from the sum of their named reserves, as
+    /// `(account, reserved, named_sum)`
+    pub fn audit_reserves(&self) -> Result<(), Vec<(AccountId, Balance, Balance)>> {
+        let reserved = self.storage.reserved.read().unwrap();
+        let named = self.storage.named_reserves.read().unwrap();
+
+        let accounts: HashSet<AccountId> = reserved.keys().chain(named.keys()).copied().collect();
+        let mut mismatches: Vec<(AccountId, Balance, Balance)> = accounts
+            .into_iter()
+            .filter_map(|who| {
+                let aggregate = reserved.get(&who).copied().unwrap_or(0);
+                let named_sum = named
+                    .get(&who)
+                    .map_or(0, |reserves| reserves.values().fold(0, |sum: Balance, amount| sum.saturating_add(*amount)));
+                (aggregate != named_sum).then_some((who, aggregate, named_sum))
+            })
+            .collect();
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        mismatches.sort_unstable();
+        Err(mismatches)
+    }
+
+    #[cfg(test)]
+    fn set_reserved(&self, who: AccountId, amount: Balance) {
+        self.storage.reserved.write().unwrap().insert(who, amount);
+    }
+
+    /// Accounts holding reserved funds with no free balance, no pending claim backing the reserve and no
+    /// active vesting lock
+    pub fn orphaned_reserves(&self) -> Vec<(AccountId, Balance)> {
+        let now = self.block_number();
+        let claims = self.storage.claims.read().unwrap();
+        let balances = self.storage.balances.read().unwrap();
+        let reserved = self.storage.reserved.read().unwrap();
+        let vesting = self.storage.vesting.read().unwrap();
+
+        let mut orphaned: Vec<(AccountId, Balance)> = reserved
+            .iter()
+            .filter(|(who, amount)| {
+                **amount > 0
+                    && balances.get(who).copied().unwrap_or(0) == 0
+                    && !claims.values().any(|pending| pending.from == **who)
+                    && vesting.get(who).is_none_or(|schedule| schedule.locked_at(now) == 0)
+            })
+            .map(|(who, amount)| (*who, *amount))
+            .collect();
+        orphaned.sort_unstable();
+        orphaned
+    }
+
+    /// Get total issuance
+    pub fn total_issuance(&self) -> Balance {
+        *self.storage.total_issuance.read().unwrap()
+    }
+
+    /// Mint into `pool` whatever is needed to bring total issuance up to `target_issuance`, returning the
+    /// minted amount
+    pub fn mint_to_target(&self, pool: AccountId, target_issuance: Balance) -> Result<Balance, Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_issuance_unfrozen()?;
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        let gap = target_issuance.checked_sub(*total).ok_or(Error::InvalidValue)?;
+        if gap == 0 {
+            return Ok(0);
+        }
+        let new_balance = balances.get(&pool).copied().unwrap_or(0).checked_add(gap).ok_or(Error::Overflow)?;
+        self.ensure_within_cap(new_balance)?;
+        self.insert_balance(&mut balances, pool, new_balance);
+        *total = target_issuance;
+
+        self.emit_event(Event::Deposit { who: pool, amount: gap });
+        Ok(gap)
+    }
+
+    /// Mint `amount` of a non-native asset to an account
+    pub fn deposit_asset(&self, who: AccountId, asset: AssetId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        ensure_foreign_asset(asset)?;
+        let mut balances = self.storage.asset_balances.write().unwrap();
+        let mut issuance = self.storage.asset_issuance.write().unwrap();
+
+        let new_balance = balances.get(&(who, asset)).copied().unwrap_or(0).checked_add(amount).ok_or(Error::Overflow)?;
+        let new_issuance = issuance.get(&asset).copied().unwrap_or(0).checked_add(amount).ok_or(Error::Overflow)?;
+        balances.insert((who, asset), new_balance);
+        issuance.insert(asset, new_issuance);
+
+        self.emit_event(Event::AssetDeposit { who, asset, amount });
+        Ok(())
+    }
+
+    /// Burn `amount` of a non-native asset from an account, reaping the holding if it falls below the
+    /// asset's existential deposit
+    pub fn withdraw_asset(&self, who: AccountId, asset: AssetId, amount: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        let mut balances = self.storage.asset_balances.write().unwrap();
+        let mut issuance = self.storage.asset_issuance.write().unwrap();
+
+        let balance = balances.get(&(who, asset)).copied().ok_or(Error::AccountNotFound)?;
+        if balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        let new_issuance = issuance.get(&asset).copied().unwrap_or(0).checked_sub(amount).ok_or(Error::Underflow)?;
+        balances.insert((who, asset), balance - amount);
+        issuance.insert(asset, new_issuance);
+
+        self.emit_event(Event::AssetWithdraw { who, asset, amount });
+        self.reap_asset_dust(&mut balances, &mut issuance, who, asset);
+        Ok(())
+    }
+
+    /// Set the existential deposit of a non-native asset; the native asset uses the configured one
+    pub fn set_asset_existential_deposit(&self, asset: AssetId, ed: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        ensure_foreign_asset(asset)?;
+        self.storage.asset_existential_deposits.write().unwrap().insert(asset, ed);
+        Ok(())
+    }
+
+    /// Existential deposit of an asset, zero for non-native assets without one
+    pub fn asset_existential_deposit(&self, asset: AssetId) -> Balance {
+        if asset == NATIVE_ASSET {
+            return self.config.existential_deposit;
+        }
+        self.storage.asset_existential_deposits.read().unwrap().get(&asset).copied().unwrap_or(0)
+    }
+
+    // Remove `who`'s holding of `asset` and burn what is left if it is below the asset's existential
+    // deposit
+    fn reap_asset_dust(
+        &self,
+        balances: &mut HashMap<(AccountId, AssetId), Balance>,
+        issuance: &mut HashMap<AssetId, Balance>,
+        who: AccountId,
+        asset: AssetId,
+    ) {
+        let dust = match balances.get(&(who, asset)) {
+            Some(balance) if *balance < self.asset_existential_deposit(asset) => *balance,
+            _ => return,
+        };
+        balances.remove(&(who, asset));
+        if let Some(supply) = issuance.get_mut(&asset) {
+            *supply = supply.saturating_sub(dust);
+        }
+        self.emit_event(Event::AssetReaped { who, asset, dust });
+    }
+
+    /// Balance of a non-native asset held by an account
+    pub fn asset_balance_of(&self, who: AccountId, asset: AssetId) -> Balance {
+        self.storage.asset_balances.read().unwrap().get(&(who, asset)).copied().unwrap_or(0)
+    }
+
+    /// Total issuance of a non-native asset
+    pub fn asset_issuance(&self, asset: AssetId) -> Balance {
+        self.storage.asset_issuance.read().unwrap().get(&asset).copied().unwrap_or(0)
+    }
+
+    /// Let `convert` turn each unit of `from` into `numerator / denominator` units of `to`
+    pub fn set_conversion_rate(&self, from: AssetId, to: AssetId, numerator: Balance, denominator: Balance) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        ensure_foreign_asset(from)?;
+        ensure_foreign_asset(to)?;
+        if from == to || numerator == 0 || denominator == 0 {
+            return Err(Error::InvalidValue);
+        }
+        self.storage.conversion_rates.write().unwrap().insert((from, to), (numerator, denominator));
+        Ok(())
+    }
+
+    /// Burn `amount` of `from_asset` and mint its value in `to_asset` at the configured rate, rounded
+    /// down, returning the amount produced
+    pub fn convert(&self, who: AccountId, from_asset: AssetId, to_asset: AssetId, amount: Balance) -> Result<Balance, Error> {
+        let _guard = self.lock_mutations();
+        let mut balances = self.storage.asset_balances.write().unwrap();
+        let mut issuance = self.storage.asset_issuance.write().unwrap();
+        let rates = self.storage.conversion_rates.read().unwrap();
+
+        let (numerator, denominator) = rates.get(&(from_asset, to_asset)).copied().ok_or(Error::InvalidValue)?;
+        let produced = mul_div(amount, numerator, denominator).ok_or(Error::Overflow)?;
+        // Converting dust that rounds to nothing would only destroy it
+        if produced == 0 {
+            return Err(Error::InvalidValue);
+        }
+        let from_balance = balances.get(&(who, from_asset)).copied().unwrap_or(0);
+        if from_balance < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        let to_balance = balances.get(&(who, to_asset)).copied().unwrap_or(0).checked_add(produced).ok_or(Error::Overflow)?;
+        let to_issuance = issuance.get(&to_asset).copied().unwrap_or(0).checked_add(produced).ok_or(Error::Overflow)?;
+        let from_issuance = issuance.get(&from_asset).copied().unwrap_or(0).checked_sub(amount).ok_or(Error::Underflow)?;
+
+        balances.insert((who, from_asset), from_balance - amount);
+        balances.insert((who, to_asset), to_balance);
+        issuance.insert(from_asset, from_issuance);
+        issuance.insert(to_asset, to_issuance);
+
+        self.emit_event(Event::AssetConverted { who, from_asset, to_asset, amount, produced });
+        self.reap_asset_dust(&mut balances, &mut issuance, who, from_asset);
+        Ok(produced)
+    }
+
+    /// Burn `bps` basis points of every free balance, rounding each reduction down, and return the total burned
+    ///
+    /// Fails with `InvalidValue` above 10_000 basis points, and with `IssuanceFrozen` while issuance is
+    /// frozen, since the burn changes it
+    pub fn haircut(&self, bps: u16) -> Result<Balance, Error> {
+        let _guard = self.lock_mutations();
+        if bps > 10_000 {
+            return Err(Error::InvalidValue);
+        }
+        self.ensure_issuance_unfrozen()?;
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        let mut burned: Balance = 0;
+        for balance in balances.values_mut() {
+            let cut = bps_of(*balance, bps);
+            *balance -= cut;
+            burned += cut;
+        }
+        *total = total.saturating_sub(burned);
+
+        self.emit_event(Event::Haircut { bps, total: burned });
+        Ok(burned)
+    }
+
+    /// Recompute total issuance from free and reserved balances and overwrite the stored value
+    pub fn repair_issuance(&self) -> Balance {
+        let _guard = self.lock_mutations();
+        let balances = self.storage.balances.read().unwrap();
+        let reserved = self.storage.reserved.read().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        let old = *total;
+        let new = balances
+            .values()
+            .chain(reserved.values())
+            .fold(0, |sum: Balance, balance| sum.saturating_add(*balance));
+        *total = new;
+
+        self.emit_event(Event::IssuanceRepaired { old, new });
+        new
+    }
+
+    #[cfg(test)]
+    fn set_total_issuance(&self, value: Balance) {
+        *self.storage.total_issuance.write().unwrap() = value;
+    }
+
+    /// Compute aggregate balance statistics under a single read lock
+    pub fn statistics(&self) -> Statistics {
+        let balances = self.storage.balances.read().unwrap();
+        let total_issuance = *self.storage.total_issuance.read().unwrap();
+
+        let mut values: Vec<Balance> = balances.values().copied().collect();
+        if values.is_empty() {
+            return Statistics { total_issuance, ..Statistics::default() };
+        }
+        values.sort_unstable();
+
+        let count = values.len();
+        let sum = values.iter().fold(0, |sum: Balance, balance| sum.saturating_add(*balance));
+        let median_balance = if count.is_multiple_of(2) {
+            let (low, high) = (values[count / 2 - 1], values[count / 2]);
+            low + (high - low) / 2
+        } else {
+            values[count / 2]
+        };
+
+        Statistics {
+            account_count: count,
+            total_issuance,
+            mean_balance: sum / count as Balance,
+            median_balance,
+            max_balance: values[count - 1],
+            min_nonzero_balance: values.iter().copied().find(|balance| *balance > 0),
+        }
+    }
+
+    /// Smallest holding, free plus reserved, such that accounts holding at most that much hold at least
+    /// half of total issuance
+    pub fn weighted_median_balance(&self) -> Option<Balance> {
+        let balances = self.storage.balances.read().unwrap();
+        let reserved = self.storage.reserved.read().unwrap();
+        let mut holdings: HashMap<AccountId, Balance> = balances.clone();
+        for (who, amount) in reserved.iter() {
+            let held = holdings.entry(*who).or_insert(0);
+            *held = held.saturating_add(*amount);
+        }
+        drop((balances, reserved));
+        let mut values: Vec<Balance> = holdings.into_values().collect();
+        values.sort_unstable();
+
+        let half = values.iter().fold(0, |sum: Balance, balance| sum.saturating_add(*balance)).div_ceil(2);
+        let mut held: Balance = 0;
+        values.into_iter().find(|balance| {
+            held = held.saturating_add(*balance);
+            held >= half
+        })
+    }
+
+    /// Export free, reserved and named reserve balances of every account, along with the idempotency keys
+    /// already used
+    pub fn export_state(&self) -> StateSnapshot {
+        let _guard = self.lock_mutations();
+        StateSnapshot {
+            balances: self.storage.balances.read().unwrap().clone(),
+            reserved: self.storage.reserved.read().unwrap().clone(),
+            named_reserves: self.storage.named_reserves.read().unwrap().clone(),
+            seen_keys: self.storage.seen_keys.read().unwrap().clone(),
+        }
+    }
+
+    /// Replace all account state and used idempotency keys with the snapshot and recompute total issuance
+    pub fn import_state(&self, snapshot: StateSnapshot) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        let imported_named = imported_named_reserves(&snapshot)?;
+        let mut seen_keys = self.storage.seen_keys.write().unwrap();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        *total = sum_balances(snapshot.balances.values().chain(snapshot.reserved.values()))?;
+        *named = imported_named;
+        *balances = snapshot.balances;
+        *reserved = snapshot.reserved;
+        *seen_keys = snapshot.seen_keys;
+        self.reconcile_creation_order(&balances);
+        Ok(())
+    }
+
+    /// Merge the snapshot into existing state, resolving overlapping accounts with `strategy`
+    ///
+    /// Idempotency keys are always combined, so a key used on either side stays used.
+    pub fn import_state_merge(&self, snapshot: StateSnapshot, strategy: MergeStrategy) -> Result<(), Error> {
+        let _guard = self.lock_mutations();
+        let imported_named = imported_named_reserves(&snapshot)?;
+        let mut seen_keys = self.storage.seen_keys.write().unwrap();
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut reserved = self.storage.reserved.write().unwrap();
+        let mut named = self.storage.named_reserves.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        let merge = |existing: &HashMap<AccountId, Balance>, incoming: HashMap<AccountId, Balance>| {
+            let mut merged = existing.clone();
+            for (who, amount) in incoming {
+                let value = match (merged.get(&who).copied(), strategy) {
+                    (None, _) | (Some(_), MergeStrategy::Replace) => amount,
+                    (Some(current), MergeStrategy::Sum) => current.checked_add(amount).ok_or(Error::Overflow)?,
+                    (Some(current), MergeStrategy::KeepExisting) => current,
+                };
+                merged.insert(who, value);
+            }
+            Ok(merged)
+        };
+        let merged_balances = merge(&balances, snapshot.balances)?;
+        let merged_reserved = merge(&reserved, snapshot.reserved.clone())?;
+
+        *total = sum_balances(merged_balances.values().chain(merged_reserved.values()))?;
+        for (who, buckets) in imported_named {
+            let existing = reserved.contains_key(&who);
+            match (existing, strategy) {
+                (true, MergeStrategy::KeepExisting) => {}
+                (true, MergeStrategy::Sum) => {
+                    let account_reserves = named.entry(who).or_default();
+                    for (id, amount) in buckets {
+                        let bucket = account_reserves.entry(id).or_insert(0);
+                        *bucket = bucket.saturating_add(amount);
+                    }
+                }
+                (false, _) | (true, MergeStrategy::Replace) => {
+                    named.insert(who, buckets);
+                }
+            }
+        }
+        *balances = merged_balances;
+        *reserved = merged_reserved;
+        seen_keys.extend(snapshot.seen_keys);
+        self.reconcile_creation_order(&balances);
+        Ok(())
+    }
+
+    /// Advance to next block
+    pub fn next_block(&self) {
+        let _guard = self.lock_mutations();
+        match self.config.fee_sweep {
+            Some(treasury) => {
+                self.settle_block_fees(Some(treasury), |_, amount| Event::FeesSwept { amount });
+            }
+            None => {
+                let author = self.storage.author.read().unwrap();
+                self.settle_block_fees(*author, |who, amount| Event::AuthorSettled { who, amount });
+            }
+        }
+        let number = {
+            let mut block_number = self.storage.block_number.write().unwrap();
+            let closing_issuance = self.total_issuance();
+            self.storage.issuance_history.write().unwrap().push(closing_issuance);
+            *block_number += 1;
+            self.emit_event(Event::NewBlock { number: *block_number });
+            *block_number
+        };
+        self.refund_expired_claims(number);
+        self.execute_scheduled_transfers(number);
+        self.accrue_interest();
+        self.apply_demurrage();
+    }
+
+    fn apply_demurrage(&self) {
+        if self.config.demurrage_rate == 0 || self.ensure_issuance_unfrozen().is_err() {
+            return;
+        }
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+
+        let mut accounts: Vec<AccountId> = balances.keys().copied().collect();
+        accounts.sort_unstable();
+        for who in accounts {
+            let balance = balances.get_mut(&who).unwrap();
+            let decay = bps_of(*balance, self.config.demurrage_rate);
+            if decay == 0 {
+                continue;
+            }
+            *balance -= decay;
+            *total = total.saturating_sub(decay);
+            self.emit_event(Event::Demurrage { who, amount: decay });
+        }
+    }
+
+    /// Schedule a transfer to run at block `at`; items due in the same block run highest priority first,
+    /// then in scheduling order
+    pub fn schedule_transfer(
+        &self,
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+        at: BlockNumber,
+        priority: u8,
+    ) -> Result<ScheduleId, Error> {
+        let _guard = self.lock_mutations();
+        self.ensure_unlocked(from)?;
+        if at <= self.block_number() {
+            return Err(Error::InvalidValue);
+        }
+
+        let mut scheduled = self.storage.scheduled.write().unwrap();
+        let mut next_id = self.storage.next_schedule_id.write().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+        scheduled.push(ScheduledTransfer { id, from, to, amount, at, priority });
+        Ok(id)
+    }
+
+    fn execute_scheduled_transfers(&self, block_number: BlockNumber) {
+        let mut due: Vec<ScheduledTransfer> = {
+            let mut scheduled = self.storage.scheduled.write().unwrap();
+            let (due, pending) = scheduled.drain(..).partition(|item| item.at <= block_number);
+            *scheduled = pending;
+            due
+        };
+        due.sort_by_key(|item| (std::cmp::Reverse(item.priority), item.id));
+
+        for item in due {
+            if let Err(error) = self.transfer(item.from, item.to, item.amount) {
+                self.emit_event(Event::ScheduledTransferFailed { id: item.id, error });
+            }
+        }
+    }
+
+    fn accrue_interest(&self) {
+        if self.config.interest_rate == 0 || self.ensure_issuance_unfrozen().is_err() {
+            return;
+        }
+        let mut balances = self.storage.balances.write().unwrap();
+        let mut total = self.storage.total_issuance.write().unwrap();
+        let mut earned = self.storage.interest_earned.write().unwrap();
+
+        let mut accounts: Vec<AccountId> = balances.keys().copied().collect();
+        accounts.sort_unstable();
+        let mut dust: Balance = 0;
+        for who in accounts {
+            let balance = balances[&who];
+            dust = dust.saturating_add(bps_fraction(balance, self.config.interest_rate));
+            let mut interest = bps_of(balance, self.config.interest_rate);
+            // Interest only tops an account up to its cap
+            if let Some(max) = self.config.max_balance_per_account {
+                interest = interest.min(max.saturating_sub(balance));
+            }
+            interest = interest.min(Balance::MAX - *total);
+            if interest == 0 {
+                continue;
+            }
+
+            balances.insert(who, balance + interest);
+            *total += interest;
+            let account_earned = earned.entry(who).or_insert(0);
+            *account_earned = account_earned.saturating_add(interest);
+            self.emit_event(Event::Interest { who, amount: interest });
+        }
+        let collected = self.collect_dust(&mut balances, &mut total, dust);
+        if let Some(who) = self.config.remainder_account.filter(|_| collected > 0) {
+            self.emit_event(Event::RemainderCollected { who, amount: collected });
+        }
+    }
+
+    /// Cumulative interest credited to an account
+    pub fn interest_earned_of(&self, who: AccountId) -> Balance {
+        self.storage.interest_earned.read().unwrap().get(&who).copied().unwrap_or(0)
+    }
+
+    /// Total issuance at the end of `block`, or the live value for the current block
+    pub fn issuance_at(&self, block: BlockNumber) -> Option<Balance> {
+        let history = self.storage.issuance_history.read().unwrap();
+        match (block as usize).cmp(&history.len()) {
+            std::cmp::Ordering::Less => Some(history[block as usize]),
+            std::cmp::Ordering::Equal => Some(self.total_issuance()),
+            std::cmp::Ordering::Greater => None,
+        }
+    }
+
+    /// Signed change in total issuance between the ends of two blocks
+    pub fn issuance_delta(&self, from_block: BlockNumber, to_block: BlockNumber) -> Result<i128, Error> {
+        let from = self.issuance_at(from_block).ok_or(Error::InvalidValue)?;
+        let to = self.issuance_at(to_block).ok_or(Error::InvalidValue)?;
+        Ok(to_signed(to) - to_signed(from))
+    }
+
+    /// Get current block number
+    pub fn block_number(&self) -> BlockNumber {
+        *self.storage.block_number.read().unwrap()
+    }
+
+    // Callers must still hold the lock guarding the state change being recorded, so that
+    // sequence numbers follow the order in which mutations were serialized
+    fn emit_event(&self, event: Event) {
+        let mut events = self.storage.events.write().unwrap();
+        let mut next_seq = self.storage.next_event_seq.write().unwrap();
+        events.push(EventRecord { seq: *next_seq, event });
+        *next_seq += 1;
+    }
+
+    /// Get all events
+    pub fn events(&self) -> Vec<Event> {
+        self.storage.events.read().unwrap().iter().map(|record| record.event.clone()).collect()
+    }
+
+    /// Get all events together with their sequence numbers
+    pub fn event_records(&self) -> Vec<EventRecord> {
+        self.storage.events.read().unwrap().clone()
+    }
+
+    /// Get the events whose sequence numbers fall in the inclusive range
+    pub fn events_by_seq(&self, from_seq: u64, to_seq: u64) -> Vec<Event> {
+        self.storage
+            .events
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|record| (from_seq..=to_seq).contains(&record.seq))
+            .map(|record| record.event.clone())
+            .collect()
+    }
+
+    /// Get a page of the events matching `predicate`, skipping the first `offset` matches
+    pub fn query_events(&self, predicate: impl Fn(&Event) -> bool, offset: usize, limit: usize) -> Vec<Event> {
+        self.storage
+            .events
+            .read()
+            .unwrap()
+            .iter()
+            .map(|record| &record.event)
+            .filter(|event| predicate(event))
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    // Pair each event with the block it was emitted in, using `NewBlock` events as boundaries
+    fn events_with_blocks(&self) -> Vec<(BlockNumber, Event)> {
+        let mut block = 0;
+        self.storage
+            .events
+            .read()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                if let Event::NewBlock { number } = record.event {
+                    block = number;
+                }
+                (block, record.event.clone())
+            })
+            .collect()
+    }
+
+    /// Summarize the events of `block`, or `None` if the block has not started yet
+    pub fn block_digest(&self, block: BlockNumber) -> Option<BlockDigest> {
+        if block > self.block_number() {
+            return None;
+        }
+        let mut digest = BlockDigest::default();
+        for (_, event) in self.events_with_blocks().into_iter().filter(|(number, _)| *number == block) {
+            match event {
+                Event::Deposit { amount, .. } => digest.deposited = digest.deposited.saturating_add(amount),
+                Event::Withdraw { amount, .. } => digest.withdrawn = digest.withdrawn.saturating_add(amount),
+                Event::Transfer { amount, .. } => digest.transferred = digest.transferred.saturating_add(amount),
+                _ => {}
+            }
+            *digest.event_counts.entry(event.name()).or_insert(0) += 1;
+            digest.accounts_touched.extend(event.accounts());
+        }
+        Some(digest)
+    }
+
+    /// Net signed change in free balance per account over the inclusive block range
+    pub fn account_deltas(&self, from_block: BlockNumber, to_block: BlockNumber) -> HashMap<AccountId, i128> {
+        let mut deltas: HashMap<AccountId, i128> = HashMap::new();
+        let mut apply = |who: AccountId, delta: i128| {
+            let entry = deltas.entry(who).or_insert(0);
+            *entry = entry.saturating_add(delta);
+        };
+
+        for (block, event) in self.events_with_blocks() {
+            if block < from_block || block > to_block {
+                continue;
+            }
+            match event {
+                Event::Deposit { who, amount }
+                | Event::RemainderCollected { who, amount }
+                | Event::Interest { who, amount }
+                | Event::FeeCollected { who, amount }
+                | Event::Overdrawn { who, amount }
+                | Event::AuthorSettled { who, amount }
+                | Event::Unreserved { who, amount }
+                | Event::ClaimRefunded { who, amount, .. } => apply(who, to_signed(amount)),
+                Event::FeesSwept { amount } => {
+                    if let Some(treasury) = self.config.fee_sweep {
+                        apply(treasury, to_signed(amount));
+                    }
+                }
+                Event::NetSettled { who, delta } => apply(who, delta),
+                Event::Withdraw { who, amount }
+                | Event::FeeCharged { who, amount }
+                | Event::Demurrage { who, amount }
+                | Event::DustLost { who, amount }
+                | Event::OverdraftRepaid { who, amount }
+                | Event::Reserved { who, amount } => apply(who, -to_signed(amount)),
+                Event::Transfer { from, to, amount } | Event::DustCollected { from, to, amount } => {
+                    apply(from, -to_signed(amount));
+                    apply(to, to_signed(amount));
+                }
+                _ => {}
+            }
+        }
+        deltas
+    }
+
+    /// Replay an event log against a fresh pallet, reporting the first event it could not apply
+    pub fn validate_event_log(events: &[Event]) -> Result<(), (usize, Error)> {
+        let pallet = BalancesPallet::new();
+        for (index, event) in events.iter().enumerate() {
+            let result = match *event {
+                Event::Deposit { who, amount }
+                | Event::RemainderCollected { who, amount }
+                | Event::Interest { who, amount }
+                | Event::FeeCollected { who, amount }
+                | Event::Overdrawn { who, amount }
+                | Event::AuthorSettled { who, amount } => pallet.deposit(who, amount),
+                Event::Withdraw { who, amount }
+                | Event::FeeCharged { who, amount }
+                | Event::Demurrage { who, amount }
+                | Event::DustLost { who, amount }
+                | Event::OverdraftRepaid { who, amount } => pallet.withdraw(who, amount),
+                Event::Transfer { from, to, amount } | Event::DustCollected { from, to, amount } => {
+                    pallet.transfer(from, to, amount)
+                }
+                Event::Reserved { who, amount } => pallet.reserve(who, amount),
+                Event::Unreserved { who, amount } | Event::ClaimRefunded { who, amount, .. } => {
+                    pallet.unreserve(who, amount)
+                }
+                Event::NetSettled { who, delta } if delta < 0 => pallet.withdraw(who, delta.unsigned_abs()),
+                Event::NetSettled { who, delta } => pallet.deposit(who, delta.unsigned_abs()),
+                Event::Haircut { bps, total } => match pallet.haircut(bps) {
+                    Ok(burned) if burned != total => Err(Error::InvalidValue),
+                    result => result.map(|_| ()),
+                },
+                // The treasury is configuration rather than part of the log, so the credit can't be replayed
+                Event::NewBlock { .. }
+                | Event::FeesSwept { .. }
+                | Event::IssuanceRepaired { .. }
+                | Event::Memo { .. }
+                | Event::AccountReaped { .. }
+                | Event::ScheduledTransferFailed { .. } => Ok(()),
+                // Other assets don't affect native balances
+                Event::AssetDeposit { .. }
+                | Event::AssetConverted { .. }
+                | Event::AssetWithdraw { .. }
+                | Event::AssetReaped { .. } => Ok(()),
+            };
+            result.map_err(|error| (index, error))?;
+        }
+        Ok(())
+    }
+
+    /// Accounts whose net outflow over the last `window` blocks, including the current one, exceeds `threshold`
+    pub fn detect_anomalies(&self, window: BlockNumber, threshold: Balance) -> Vec<(AccountId, Balance)> {
+        if window == 0 {
+            return Vec::new();
+        }
+        let now = self.block_number();
+        let from_block = now.saturating_sub(window - 1);
+
+        let mut flagged: Vec<(AccountId, Balance)> = self
+            .account_deltas(from_block, now)
+            .into_iter()
+            .filter(|(_, delta)| *delta < 0)
+            .map(|(who, delta)| (who, delta.unsigned_abs()))
+            .filter(|(_, outflow)| *outflow > threshold)
+            .collect();
+        flagged.sort_unstable();
+        flagged
+    }
+
+    /// Deposits, withdrawals and transfers per second over the last `window_blocks` blocks, including the
+    /// current one
+    ///
+    /// Returns zero when no block time is configured.
+    pub fn throughput(&self, window_blocks: BlockNumber) -> f64 {
+        let block_time_ms = match self.config.block_time_ms {
+            Some(millis) if millis > 0 && window_blocks > 0 => millis,
+            _ => return 0.0,
+        };
+        let from_block = self.block_number().saturating_sub(window_blocks - 1);
+
+        let operations = self
+            .events_with_blocks()
+            .into_iter()
+            .filter(|(block, event)| {
+                *block >= from_block
+                    && matches!(event, Event::Transfer { .. } | Event::Deposit { .. } | Event::Withdraw { .. })
+            })
+            .count();
+        let seconds = f64::from(window_blocks) * block_time_ms as f64 / 1000.0;
+        operations as f64 / seconds
+    }
+
+    /// Largest transfer on record as `(from, to, amount, block)`, preferring the earliest on ties
+    pub fn largest_transfer(&self) -> Option<(AccountId, AccountId, Balance, BlockNumber)> {
+        let mut largest: Option<(AccountId, AccountId, Balance, BlockNumber)> = None;
+        for (block, event) in self.events_with_blocks() {
+            if let Event::Transfer { from, to, amount } = event {
+                if largest.is_none_or(|(_, _, max, _)| amount > max) {
+                    largest = Some((from, to, amount, block));
+                }
+            }
+        }
+        largest
+    }
+
+    /// Net issuance implied by the event log, for cross-checking against `total_issuance`
+    pub fn issuance_from_events(&self) -> i128 {
+        self.storage.events.read().unwrap().iter().fold(0, |net: i128, record| {
+            let delta = match record.event {
+                Event::Deposit { amount, .. }
+                | Event::Interest { amount, .. }
+                | Event::FeeCollected { amount, .. }
+                | Event::RemainderCollected { amount, .. }
+                | Event::Overdrawn { amount, .. }
+                | Event::AuthorSettled { amount, .. }
+                | Event::FeesSwept { amount } => to_signed(amount),
+                Event::Withdraw { amount, .. }
+                | Event::FeeCharged { amount, .. }
+                | Event::Demurrage { amount, .. }
+                | Event::DustLost { amount, .. }
+                | Event::OverdraftRepaid { amount, .. }
+                | Event::Haircut { total: amount, .. } => -to_signed(amount),
+                Event::IssuanceRepaired { old, new } => to_signed(new) - to_signed(old),
+                _ => 0,
+            };
+            net.saturating_add(delta)
+        })
+    }
+
+    /// Render transfers as a Graphviz DOT digraph with edges labelled by cumulative amount
+    pub fn transfer_graph_dot(&self) -> String {
+        let mut nodes: BTreeSet<AccountId> = BTreeSet::new();
+        let mut edges: BTreeMap<(AccountId, AccountId), Balance> = BTreeMap::new();
+        for record in self.storage.events.read().unwrap().iter() {
+            match record.event {
+                Event::Deposit { who, .. } => {
+                    nodes.insert(who);
+                }
+                Event::Transfer { from, to, amount } => {
+                    nodes.insert(from);
+                    nodes.insert(to);
+                    let total = edges.entry((from, to)).or_insert(0);
+                    *total = total.saturating_add(amount);
+                }
+                _ => {}
+            }
+        }
+
+        let mut dot = String::from("digraph transfers {\n");
+        for node in nodes {
+            dot.push_str(&format!("    {};\n", node));
+        }
+        for ((from, to), amount) in edges {
+            dot.push_str(&format!("    {} -> {} [label=\"{}\"];\n", from, to, amount));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Find a chain of transfers carrying funds from `from` to `to` in at most `max_hops` hops
+    pub fn trace_flow(&self, from: AccountId, to: AccountId, max_hops: usize) -> Option<Vec<AccountId>> {
+        if from == to {
+            return Some(vec![from]);
+        }
+
+        let mut graph: HashMap<AccountId, Vec<AccountId>> = HashMap::new();
+        for record in self.storage.events.read().unwrap().iter() {
+            if let Event::Transfer { from, to, .. } = record.event {
+                let edges = graph.entry(from).or_default();
+                if !edges.contains(&to) {
+                    edges.push(to);
+                }
+            }
+        }
+
+        // Breadth-first so the first path found is also the shortest
+        let mut parents: HashMap<AccountId, AccountId> = HashMap::new();
+        let mut visited = HashSet::from([from]);
+        let mut queue = VecDeque::from([(from, 0)]);
+        while let Some((current, hops)) = queue.pop_front() {
+            if hops == max_hops {
+                continue;
+            }
+            for &next in graph.get(&current).into_iter().flatten() {
+                if !visited.insert(next) {
+                    continue;
+                }
+                parents.insert(next, current);
+                if next == to {
+                    let mut path = vec![to];
+                    let mut cursor = to;
+                    while let Some(&parent) = parents.get(&cursor) {
+                        path.push(parent);
+                        cursor = parent;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back((next, hops + 1));
+            }
+        }
+        None
+    }
+}
+
+fn sum_balances<'a>(mut balances: impl Iterator<Item = &'a Balance>) -> Result<Balance, Error> {
+    balances.try_fold(0, |sum: Balance, balance| sum.checked_add(*balance).ok_or(Error::Overflow))
+}
+
+// Named reserves of every account with a reserved balance in the snapshot, with any reserved amount the
+// snapshot's named reserves don't account for placed in the default reserve
+fn imported_named_reserves(snapshot: &StateSnapshot) -> Result<NamedReserves, Error> {
+    let unbacked = snapshot
+        .named_reserves
+        .iter()
+        .any(|(who, buckets)| !snapshot.reserved.contains_key(who) && buckets.values().any(|amount| *amount > 0));
+    if unbacked {
+        return Err(Error::InvalidValue);
+    }
+
+    let mut imported = NamedReserves::new();
+    for (who, reserved) in &snapshot.reserved {
+        let mut buckets = snapshot.named_reserves.get(who).cloned().unwrap_or_default();
+        buckets.retain(|_, amount| *amount > 0);
+        let unnamed = reserved.checked_sub(sum_balances(buckets.values())?).ok_or(Error::InvalidValue)?;
+        if unnamed > 0 {
+            let bucket = buckets.entry(DEFAULT_RESERVE).or_insert(0);
+            *bucket = bucket.checked_add(unnamed).ok_or(Error::Overflow)?;
+        }
+        if !buckets.is_empty() {
+            imported.insert(*who, buckets);
+        }
+    }
+    Ok(imported)
+}
+
+fn add_reserve(
+    reserved: &mut HashMap<AccountId, Balance>,
+    named: &mut NamedReserves,
+    who: AccountId,
+    id: ReserveId,
+    amount: Balance,
+) -> Result<(), Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let new_reserved = reserved.get(&who).copied().unwrap_or(0).checked_add(amount).ok_or(Error::Overflow)?;
+    let bucket = named.entry(who).or_default().entry(id).or_insert(0);
+    *bucket = bucket.checked_add(amount).ok_or(Error::Overflow)?;
+    reserved.insert(who, new_reserved);
+    Ok(())
+}
+
+fn remove_reserve(
+    reserved: &mut HashMap<AccountId, Balance>,
+    named: &mut NamedReserves,
+    who: AccountId,
+    id: ReserveId,
+    amount: Balance,
+) -> Result<(), Error> {
+    let reserves = named.get_mut(&who).ok_or(Error::AccountNotFound)?;
+    let bucket = reserves.get(&id).copied().unwrap_or(0);
+    if bucket < amount {
+        return Err(Error::InsufficientBalance);
+    }
+    let aggregate = reserved.get_mut(&who).ok_or(Error::Underflow)?;
+    *aggregate = aggregate.checked_sub(amount).ok_or(Error::Underflow)?;
+    if bucket == amount {
+        reserves.remove(&id);
+        if reserves.is_empty() {
+            named.remove(&who);
+        }
+    } else {
+        reserves.insert(id, bucket - amount);
+    }
+    Ok(())
+}
+
+// Split `amount` in proportion to the weights, returning the shares with the heaviest first and
+// the undistributed remainder. No shares are returned when the weights sum to zero.
+fn split_by_weight(amount: Balance, weights: &[(AccountId, u32)]) -> (Vec<(AccountId, Balance)>, Balance) {
+    let total_weight: Balance = weights.iter().map(|(_, weight)| Balance::from(*weight)).sum();
+    if total_weight == 0 {
+        return (Vec::new(), 0);
+    }
+
+    let mut ordered = weights.to_vec();
+    // Stable sort keeps the earliest collector first among equal weights
+    ordered.sort_by_key(|(_, weight)| std::cmp::Reverse(*weight));
+    let shares: Vec<(AccountId, Balance)> = ordered
+        .into_iter()
+        .map(|(who, weight)| {
+            let weight = Balance::from(weight);
+            (who, amount / total_weight * weight + amount % total_weight * weight / total_weight)
+        })
+        .collect();
+    let distributed: Balance = shares.iter().map(|(_, share)| *share).sum();
+    (shares, amount - distributed)
+}
+
+// `bps` basis points of `amount`, rounded down; split so large amounts cannot overflow
+fn bps_of(amount: Balance, bps: u16) -> Balance {
+    let bps = Balance::from(bps);
+    (amount / 10_000).saturating_mul(bps).saturating_add(amount % 10_000 * bps / 10_000)
+}
+
+// Collateral backing `debt` at `collateral_bps` basis points, rounded up so the debt is never under-backed
+fn collateral_for(debt: Balance, collateral_bps: u16) -> Balance {
+    let exact = debt % 10_000 * Balance::from(collateral_bps);
+    bps_of(debt, collateral_bps).saturating_add(Balance::from(!exact.is_multiple_of(10_000)))
+}
+
+// Native balances have their own deposit, withdraw and existential deposit
+fn ensure_foreign_asset(asset: AssetId) -> Result<(), Error> {
+    if asset == NATIVE_ASSET {
+        return Err(Error::InvalidValue);
+    }
+    Ok(())
+}
+
+// `amount * numerator / denominator` rounded down, or `None` if the result does not fit
+fn mul_div(amount: Balance, numerator: Balance, denominator: Balance) -> Option<Balance> {
+    if let Some(product) = amount.checked_mul(numerator) {
+        return Some(product / denominator);
+    }
+    // Split both factors around the denominator so only the remainders need a wide product
+    let (quotient, remainder) = (amount / denominator, amount % denominator);
+    let (whole, fraction) = (numerator / denominator, numerator % denominator);
+    quotient
+        .checked_mul(numerator)?
+        .checked_add(remainder.checked_mul(whole)?)?
+        .checked_add(mul_div_below(remainder, fraction, denominator))
+}
+
+// `a * b / denominator` rounded down for `a, b < denominator`, by shift-and-add so the product is
+// never materialised; the result is below `b` and always fits
+fn mul_div_below(a: Balance, b: Balance, denominator: Balance) -> Balance {
+    let (mut quotient, mut remainder): (Balance, Balance) = (0, 0);
+    for bit in (0..Balance::BITS).rev() {
+        quotient <<= 1;
+        if remainder >= denominator - remainder {
+            remainder -= denominator - remainder;
+            quotient += 1;
+        } else {
+            remainder <<= 1;
+        }
+        if (a >> bit) & 1 == 1 {
+            if remainder >= denominator - b {
+                remainder -= denominator - b;
+                quotient += 1;
+            } else {
+                remainder += b;
+            }
+        }
+    }
+    quotient
+}
+
+// Ten-thousandths of a unit truncated by `bps_of`
+fn bps_fraction(amount: Balance, bps: u16) -> Balance {
+    amount % 10_000 * Balance::from(bps) % 10_000
+}
+
+fn to_signed(amount: Balance) -> i128 {
+    i128::try_from(amount).unwrap_or(i128::MAX)
+}
+
+impl Default for BalancesPallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_deposit() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        assert_eq!(pallet.balance_of(1), 1000);
+        assert_eq!(pallet.total_issuance(), 1000);
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.withdraw(1, 500).unwrap();
+        assert_eq!(pallet.balance_of(1), 500);
+        assert_eq!(pallet.total_issuance(), 500);
+    }
+
+    #[test]
+    fn test_withdraw_insufficient() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        assert_eq!(pallet.withdraw(1, 200), Err(Error::InsufficientBalance));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.transfer(1, 2, 300).unwrap();
+        assert_eq!(pallet.balance_of(1), 700);
+        assert_eq!(pallet.balance_of(2), 300);
+    }
+
+    #[test]
+    fn test_events() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 50).unwrap();
+        
+        let events = pallet.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], Event::Deposit { who: 1, amount: 100 });
+        assert_eq!(events[1], Event::Transfer { from: 1, to: 2, amount: 50 });
+    }
+
+    #[test]
+    fn test_block_number() {
+        let pallet = BalancesPallet::new();
+        assert_eq!(pallet.block_number(), 0);
+        pallet.next_block();
+        assert_eq!(pallet.block_number(), 1);
+    }
+
+    #[test]
+    fn test_trace_flow() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 60).unwrap();
+        pallet.transfer(2, 3, 40).unwrap();
+
+        assert_eq!(pallet.trace_flow(1, 3, 2), Some(vec![1, 2, 3]));
+        assert_eq!(pallet.trace_flow(1, 3, 5), Some(vec![1, 2, 3]));
+        assert_eq!(pallet.trace_flow(1, 3, 1), None);
+        assert_eq!(pallet.trace_flow(3, 1, 5), None);
+    }
+
+    #[test]
+    fn test_max_balance_per_account() {
+        let pallet = BalancesPallet::new().with_max_balance_per_account(500);
+        assert_eq!(pallet.deposit(1, 1000), Err(Error::BalanceCapExceeded));
+        pallet.deposit(1, 500).unwrap();
+        pallet.deposit(2, 400).unwrap();
+
+        assert_eq!(pallet.transfer(1, 2, 200), Err(Error::BalanceCapExceeded));
+        assert_eq!(pallet.balance_of(1), 500);
+        assert_eq!(pallet.balance_of(2), 400);
+
+        pallet.transfer(1, 2, 100).unwrap();
+        assert_eq!(pallet.balance_of(1), 400);
+        assert_eq!(pallet.balance_of(2), 500);
+        assert_eq!(pallet.deposit(2, 1), Err(Error::BalanceCapExceeded));
+    }
+
+    #[test]
+    fn test_repair_issuance() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 700).unwrap();
+        pallet.deposit(2, 300).unwrap();
+        pallet.set_total_issuance(42);
+
+        assert_eq!(pallet.repair_issuance(), 1000);
+        assert_eq!(pallet.total_issuance(), 1000);
+        assert_eq!(pallet.events().last(), Some(&Event::IssuanceRepaired { old: 42, new: 1000 }));
+    }
+
+    #[test]
+    fn test_claimable_transfer_claimed() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        let id = pallet.transfer_claimable(1, 2, 40, 3).unwrap();
+        assert_eq!(pallet.balance_of(1), 60);
+        assert_eq!(pallet.reserved_balance_of(1), 40);
+
+        assert_eq!(pallet.claim(id, 3), Err(Error::InvalidValue));
+        pallet.next_block();
+        pallet.claim(id, 2).unwrap();
+        assert_eq!(pallet.balance_of(2), 40);
+        assert_eq!(pallet.reserved_balance_of(1), 0);
+        assert_eq!(pallet.total_issuance(), 100);
+        assert_eq!(pallet.claim(id, 2), Err(Error::InvalidValue));
+    }
+
+    #[test]
+    fn test_claimable_transfer_refunded_on_expiry() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        let id = pallet.transfer_claimable(1, 2, 40, 2).unwrap();
+
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(1), 60);
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(1), 100);
+        assert_eq!(pallet.reserved_balance_of(1), 0);
+        assert_eq!(pallet.events().last(), Some(&Event::ClaimRefunded { id, who: 1, amount: 40 }));
+        assert_eq!(pallet.claim(id, 2), Err(Error::InvalidValue));
+        assert_eq!(pallet.balance_of(2), 0);
+    }
+
+    #[test]
+    fn test_estimate_fee() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Percentage(250));
+        pallet.deposit(1, 10_000).unwrap();
+
+        let call = Call::Transfer { to: 2, amount: 1000 };
+        let estimate = pallet.estimate_fee(&call);
+        assert_eq!(estimate, 25);
+        assert_eq!(pallet.events().len(), 1);
+
+        pallet.dispatch(1, call).unwrap();
+        assert_eq!(pallet.balance_of(1), 10_000 - 1000 - estimate);
+        assert_eq!(pallet.total_issuance(), 10_000 - estimate);
+        assert_eq!(pallet.events().last(), Some(&Event::FeeCharged { who: 1, amount: estimate }));
+        assert_eq!(pallet.estimate_fee(&Call::Deposit { amount: 1000 }), 0);
+
+        let tiered = BalancesPallet::new().with_fee(FeeConfig::Tiered(vec![(0, 1), (500, 5), (100, 2)]));
+        assert_eq!(tiered.estimate_fee(&Call::Transfer { to: 2, amount: 50 }), 1);
+        assert_eq!(tiered.estimate_fee(&Call::Transfer { to: 2, amount: 200 }), 2);
+        assert_eq!(tiered.estimate_fee(&Call::Transfer { to: 2, amount: 900 }), 5);
+    }
+
+    #[test]
+    fn test_launch_lock() {
+        let pallet = BalancesPallet::new().with_launch_lock(2, 1);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.transfer(1, 2, 300).unwrap();
+
+        assert_eq!(pallet.transfer(2, 3, 100), Err(Error::LaunchLocked));
+        assert_eq!(pallet.deposit(2, 100), Err(Error::LaunchLocked));
+        pallet.next_block();
+        assert_eq!(pallet.transfer(2, 3, 100), Err(Error::LaunchLocked));
+
+        pallet.next_block();
+        pallet.transfer(2, 3, 100).unwrap();
+        assert_eq!(pallet.balance_of(2), 200);
+        assert_eq!(pallet.balance_of(3), 100);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let pallet = BalancesPallet::new();
+        assert_eq!(pallet.statistics(), Statistics::default());
+
+        for (who, amount) in [(1, 100), (2, 400), (3, 50), (4, 250)] {
+            pallet.deposit(who, amount).unwrap();
+        }
+        pallet.transfer(3, 4, 50).unwrap();
+
+        assert_eq!(
+            pallet.statistics(),
+            Statistics {
+                account_count: 4,
+                total_issuance: 800,
+                mean_balance: 200,
+                median_balance: 200,
+                max_balance: 400,
+                min_nonzero_balance: Some(100),
+            }
+        );
+    }
+
+    #[test]
+    fn test_atomic_batch() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        let calls = [
+            (1, Call::Transfer { to: 2, amount: 400 }),
+            (2, Call::Transfer { to: 3, amount: 100 }),
+        ];
+
+        pallet.atomic_batch(&calls, |p| p.balance_of(3) == 100).unwrap();
+        assert_eq!(pallet.balance_of(1), 600);
+        assert_eq!(pallet.balance_of(2), 300);
+        assert_eq!(pallet.balance_of(3), 100);
+
+        let events = pallet.events();
+        assert_eq!(pallet.atomic_batch(&calls, |p| p.balance_of(1) >= 500), Err(Error::PostconditionFailed));
+        assert_eq!(pallet.balance_of(1), 600);
+        assert_eq!(pallet.balance_of(2), 300);
+        assert_eq!(pallet.balance_of(3), 100);
+        assert_eq!(pallet.events(), events);
+
+        let overdraw = [(1, Call::Withdraw { amount: 100 }), (3, Call::Withdraw { amount: 500 })];
+        assert_eq!(pallet.atomic_batch(&overdraw, |_| true), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.balance_of(1), 600);
+        assert_eq!(pallet.total_issuance(), 1000);
+    }
+
+    #[test]
+    fn test_event_order_matches_mutation_order() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(1));
+        for who in 1..=4 {
+            pallet.deposit(who, 200).unwrap();
+        }
+
+        thread::scope(|scope| {
+            for who in 1..=4u64 {
+                let pallet = &pallet;
+                scope.spawn(move || {
+                    for i in 0..200u64 {
+                        let to = (who + i) % 4 + 1;
+                        let _ = pallet.transfer(who, to, (i % 7 + 1) as Balance * 10);
+                    }
+                });
+            }
+        });
+
+        let mut replayed: HashMap<AccountId, Balance> = HashMap::new();
+        for (index, record) in pallet.event_records().into_iter().enumerate() {
+            assert_eq!(record.seq, index as u64);
+            match record.event {
+                Event::Deposit { who, amount } => *replayed.entry(who).or_insert(0) += amount,
+                Event::Transfer { from, to, amount } => {
+                    let from_balance = replayed.get_mut(&from).unwrap();
+                    assert!(*from_balance >= amount, "transfer at seq {} overdraws {}", record.seq, from);
+                    *from_balance -= amount;
+                    *replayed.entry(to).or_insert(0) += amount;
+                }
+                Event::FeeCharged { who, amount } => {
+                    let balance = replayed.get_mut(&who).unwrap();
+                    assert!(*balance >= amount, "fee at seq {} overdraws {}", record.seq, who);
+                    *balance -= amount;
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        for who in 1..=4 {
+            assert_eq!(replayed.get(&who).copied().unwrap_or(0), pallet.balance_of(who));
+        }
+    }
+
+    #[test]
+    fn test_account_deltas() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 500).unwrap();
+        pallet.next_block();
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 250).unwrap();
+        pallet.transfer(2, 3, 50).unwrap();
+        pallet.next_block();
+        pallet.withdraw(3, 10).unwrap();
+
+        let deltas = pallet.account_deltas(1, 1);
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[&1], -150);
+        assert_eq!(deltas[&2], 200);
+        assert_eq!(deltas[&3], 50);
+
+        let deltas = pallet.account_deltas(0, 2);
+        assert_eq!(deltas[&1], 350);
+        assert_eq!(deltas[&3], 40);
+    }
+
+    #[test]
+    fn test_account_deltas_track_reserves() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.reserve(1, 40).unwrap();
+        assert_eq!(pallet.account_deltas(0, 0)[&1], 60);
+
+        // The sender is debited when the claimable transfer is reserved, not when it is claimed
+        pallet.transfer_claimable(1, 2, 30, 5).unwrap();
+        pallet.next_block();
+        pallet.claim(0, 2).unwrap();
+        assert_eq!(pallet.account_deltas(0, 0)[&1], 30);
+        let deltas = pallet.account_deltas(1, 1);
+        assert_eq!(deltas.get(&1).copied().unwrap_or(0), 0);
+        assert_eq!(deltas[&2], 30);
+
+        pallet.transfer_claimable(1, 2, 20, 2).unwrap();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(1), 30);
+        assert_eq!(pallet.account_deltas(1, 2)[&1], 0);
+        assert_eq!(pallet.account_deltas(0, 2)[&1], to_signed(pallet.balance_of(1)));
+    }
+
+    #[test]
+    fn test_import_state_merge() {
+        let source = BalancesPallet::new();
+        source.deposit(1, 300).unwrap();
+        source.deposit(2, 50).unwrap();
+        let snapshot = source.export_state();
+
+        let expectations = [
+            (MergeStrategy::Replace, 300),
+            (MergeStrategy::Sum, 400),
+            (MergeStrategy::KeepExisting, 100),
+        ];
+        for (strategy, expected) in expectations {
+            let pallet = BalancesPallet::new();
+            pallet.deposit(1, 100).unwrap();
+            pallet.deposit(3, 10).unwrap();
+            pallet.import_state_merge(snapshot.clone(), strategy).unwrap();
+
+            assert_eq!(pallet.balance_of(1), expected, "{:?}", strategy);
+            assert_eq!(pallet.balance_of(2), 50);
+            assert_eq!(pallet.balance_of(3), 10);
+            assert_eq!(pallet.total_issuance(), expected + 60);
+        }
+
+        let pallet = BalancesPallet::new();
+        pallet.deposit(4, 1).unwrap();
+        pallet.import_state(snapshot).unwrap();
+        assert_eq!(pallet.balance_of(4), 0);
+        assert_eq!(pallet.total_issuance(), 350);
+    }
+
+    #[test]
+    fn test_orphaned_reserves() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.deposit(2, 100).unwrap();
+        pallet.deposit(3, 100).unwrap();
+
+        pallet.reserve(1, 100).unwrap();
+        pallet.reserve(2, 60).unwrap();
+        pallet.transfer_claimable(3, 4, 100, 5).unwrap();
+        assert_eq!(pallet.orphaned_reserves(), vec![(1, 100)]);
+
+        pallet.unreserve(1, 30).unwrap();
+        assert_eq!(pallet.balance_of(1), 30);
+        assert!(pallet.orphaned_reserves().is_empty());
+    }
+
+    #[test]
+    fn test_transfer_auto_split() {
+        let pallet = BalancesPallet::new().with_max_transfer(100);
+        pallet.deposit(1, 1000).unwrap();
+        assert_eq!(pallet.transfer(1, 2, 250), Err(Error::TransferLimitExceeded));
+
+        assert_eq!(pallet.transfer_auto_split(1, 2, 250), Ok(3));
+        assert_eq!(pallet.balance_of(2), 250);
+        let legs: Vec<Balance> = pallet
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Transfer { amount, .. } => Some(amount),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(legs, vec![100, 100, 50]);
+
+        assert_eq!(pallet.transfer_auto_split(1, 2, 80), Ok(1));
+        assert_eq!(pallet.transfer_auto_split(2, 3, 400), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.balance_of(2), 330);
+        assert_eq!(BalancesPallet::new().transfer_auto_split(1, 2, 0), Err(Error::AccountNotFound));
+    }
+
+    #[test]
+    fn test_query_events_paginated() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        for to in 2..=6 {
+            pallet.transfer(1, to, 10).unwrap();
+            pallet.next_block();
+        }
+
+        let is_transfer = |event: &Event| matches!(event, Event::Transfer { .. });
+        let recipients = |page: Vec<Event>| -> Vec<AccountId> {
+            page.into_iter()
+                .map(|event| match event {
+                    Event::Transfer { to, .. } => to,
+                    other => panic!("unexpected event {:?}", other),
+                })
+                .collect()
+        };
+        assert_eq!(recipients(pallet.query_events(is_transfer, 0, 2)), vec![2, 3]);
+        assert_eq!(recipients(pallet.query_events(is_transfer, 2, 2)), vec![4, 5]);
+        assert_eq!(recipients(pallet.query_events(is_transfer, 4, 2)), vec![6]);
+        assert!(pallet.query_events(is_transfer, 6, 2).is_empty());
+    }
+
+    #[test]
+    fn test_accounts_by_creation() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(42, 100).unwrap();
+        pallet.deposit(7, 100).unwrap();
+        pallet.transfer(7, 99, 10).unwrap();
+        pallet.deposit(3, 100).unwrap();
+        pallet.deposit(42, 5).unwrap();
+        assert_eq!(pallet.accounts_by_creation(), vec![42, 7, 99, 3]);
+
+        let snapshot = StateSnapshot {
+            balances: HashMap::from([(7, 50), (5, 10), (1, 10)]),
+            ..StateSnapshot::default()
+        };
+        pallet.import_state(snapshot).unwrap();
+        assert_eq!(pallet.accounts_by_creation(), vec![7, 1, 5]);
+        pallet.deposit(42, 1).unwrap();
+        assert_eq!(pallet.accounts_by_creation(), vec![7, 1, 5, 42]);
+    }
+
+    #[test]
+    fn test_issuance_delta() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.next_block();
+        pallet.deposit(1, 50).unwrap();
+        pallet.next_block();
+        pallet.withdraw(1, 30).unwrap();
+        pallet.next_block();
+        pallet.deposit(2, 5).unwrap();
+
+        assert_eq!(pallet.issuance_delta(0, 1), Ok(50));
+        assert_eq!(pallet.issuance_delta(1, 2), Ok(-30));
+        assert_eq!(pallet.issuance_delta(0, 3), Ok(25));
+        assert_eq!(pallet.issuance_delta(0, 4), Err(Error::InvalidValue));
+    }
+
+    #[test]
+    fn test_simulate_call() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(2));
+        pallet.deposit(1, 100).unwrap();
+        let events = pallet.events();
+
+        let simulated = pallet.simulate_call(1, Call::Transfer { to: 2, amount: 40 }).unwrap();
+        assert_eq!(
+            simulated,
+            vec![
+                Event::Transfer { from: 1, to: 2, amount: 40 },
+                Event::FeeCharged { who: 1, amount: 2 },
+            ]
+        );
+        assert_eq!(pallet.balance_of(1), 100);
+        assert_eq!(pallet.balance_of(2), 0);
+        assert_eq!(pallet.total_issuance(), 100);
+        assert_eq!(pallet.events(), events);
+
+        assert_eq!(
+            pallet.simulate_call(1, Call::Transfer { to: 2, amount: 99 }),
+            Err(Error::InsufficientBalance)
+        );
+    }
+
+    #[test]
+    fn test_escrow_transfer() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 110).unwrap();
+
+        pallet.escrow_transfer(1, 2, 3, 100, 10).unwrap();
+        assert_eq!(pallet.balance_of(1), 0);
+        assert_eq!(pallet.balance_of(2), 100);
+        assert_eq!(pallet.balance_of(3), 10);
+        assert_eq!(
+            pallet.events()[1..],
+            [
+                Event::Transfer { from: 1, to: 2, amount: 100 },
+                Event::Transfer { from: 1, to: 3, amount: 10 },
+            ]
+        );
+
+        pallet.deposit(4, 105).unwrap();
+        assert_eq!(pallet.escrow_transfer(4, 2, 3, 100, 10), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.balance_of(4), 105);
+        assert_eq!(pallet.balance_of(2), 100);
+        assert_eq!(pallet.balance_of(3), 10);
+
+        let capped = BalancesPallet::new().with_max_balance_per_account(50);
+        capped.deposit(1, 50).unwrap();
+        capped.deposit(3, 45).unwrap();
+        assert_eq!(capped.escrow_transfer(1, 2, 3, 30, 10), Err(Error::BalanceCapExceeded));
+        assert_eq!(capped.balance_of(1), 50);
+        assert_eq!(capped.balance_of(2), 0);
+    }
+
+    #[test]
+    fn test_freeze_issuance() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.freeze_issuance();
+
+        assert_eq!(pallet.deposit(1, 10), Err(Error::IssuanceFrozen));
+        assert_eq!(pallet.withdraw(1, 10), Err(Error::IssuanceFrozen));
+        pallet.transfer(1, 2, 40).unwrap();
+        assert_eq!(pallet.balance_of(2), 40);
+        assert_eq!(pallet.total_issuance(), 100);
+
+        pallet.unfreeze_issuance();
+        pallet.deposit(1, 10).unwrap();
+        assert_eq!(pallet.total_issuance(), 110);
+
+        let with_fee = BalancesPallet::new().with_fee(FeeConfig::Flat(1));
+        with_fee.deposit(1, 100).unwrap();
+        with_fee.freeze_issuance();
+        assert_eq!(with_fee.transfer(1, 2, 40), Err(Error::IssuanceFrozen));
+    }
+
+    #[test]
+    fn test_remainder_account() {
+        let pallet = BalancesPallet::new()
+            .with_fee(FeeConfig::Percentage(100))
+            .with_remainder_account(9);
+        pallet.deposit(1, 20_000).unwrap();
+
+        // 1% of 1010 is 10.1: 11 is charged, 10 is burned and the rounded-up unit goes to the remainder account
+        for _ in 0..10 {
+            pallet.transfer(1, 2, 1010).unwrap();
+        }
+        pallet.transfer(1, 2, 1000).unwrap();
+
+        assert_eq!(pallet.balance_of(9), 10);
+        assert_eq!(pallet.balance_of(1), 20_000 - 10 * (1010 + 11) - (1000 + 10));
+        assert_eq!(pallet.total_issuance(), 20_000 - 110);
+        assert_eq!(pallet.estimate_fee(&Call::Transfer { to: 2, amount: 1010 }), 11);
+        assert_eq!(pallet.issuance_from_events(), to_signed(pallet.total_issuance()));
+
+        // 1% interest on 150 and then 151 truncates 0.5 and 0.51
+        let pallet = BalancesPallet::new().with_interest_rate(100).with_remainder_account(9);
+        pallet.deposit(1, 150).unwrap();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(9), 0);
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(1), 152);
+        assert_eq!(pallet.balance_of(9), 1);
+        assert_eq!(pallet.total_issuance(), 153);
+    }
+
+    #[test]
+    fn test_validate_event_log() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(1));
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 30).unwrap();
+        let id = pallet.transfer_claimable(2, 3, 10, 2).unwrap();
+        pallet.claim(id, 3).unwrap();
+        pallet.transfer_claimable(1, 3, 20, 1).unwrap();
+        pallet.next_block();
+        pallet.withdraw(1, 50).unwrap();
+        assert_eq!(BalancesPallet::validate_event_log(&pallet.events()), Ok(()));
+
+        let log = [
+            Event::Deposit { who: 1, amount: 100 },
+            Event::Transfer { from: 1, to: 2, amount: 40 },
+            Event::Withdraw { who: 2, amount: 30 },
+            Event::Withdraw { who: 1, amount: 70 },
+            Event::Deposit { who: 1, amount: 500 },
+        ];
+        assert_eq!(BalancesPallet::validate_event_log(&log), Err((3, Error::InsufficientBalance)));
+    }
+
+    #[test]
+    fn test_interest_earned() {
+        let pallet = BalancesPallet::new().with_interest_rate(150);
+        pallet.deposit(1, 10_000).unwrap();
+        pallet.deposit(2, 333).unwrap();
+        for _ in 0..5 {
+            pallet.next_block();
+        }
+
+        for who in [1, 2] {
+            let from_events: Balance = pallet
+                .events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::Interest { who: credited, amount } if credited == who => Some(amount),
+                    _ => None,
+                })
+                .sum();
+            assert!(from_events > 0);
+            assert_eq!(pallet.interest_earned_of(who), from_events);
+        }
+        assert_eq!(pallet.balance_of(1), 10_000 + pallet.interest_earned_of(1));
+        assert_eq!(pallet.interest_earned_of(3), 0);
+        assert_eq!(pallet.total_issuance(), pallet.balance_of(1) + pallet.balance_of(2));
+    }
+
+    #[test]
+    fn test_haircut() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(2, 255).unwrap();
+        pallet.deposit(3, 9).unwrap();
+
+        let burned = pallet.haircut(1000).unwrap();
+        assert_eq!(pallet.balance_of(1), 900);
+        assert_eq!(pallet.balance_of(2), 230);
+        assert_eq!(pallet.balance_of(3), 9);
+        assert_eq!(burned, 125);
+        assert_eq!(pallet.total_issuance(), 1264 - burned);
+        assert_eq!(pallet.events().last(), Some(&Event::Haircut { bps: 1000, total: 125 }));
+        assert_eq!(BalancesPallet::validate_event_log(&pallet.events()), Ok(()));
+        assert_eq!(pallet.haircut(20_000), Err(Error::InvalidValue));
+
+        pallet.freeze_issuance();
+        assert_eq!(pallet.haircut(1000), Err(Error::IssuanceFrozen));
+        assert_eq!(pallet.balance_of(1), 900);
+    }
+
+    #[test]
+    fn test_fee_collectors() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(100));
+        pallet.deposit(1, 1000).unwrap();
+        pallet.set_fee_collectors(&[(7, 1), (8, 3)]);
+
+        pallet.transfer(1, 2, 100).unwrap();
+        assert_eq!(pallet.balance_of(7), 25);
+        assert_eq!(pallet.balance_of(8), 75);
+        assert_eq!(pallet.balance_of(1), 800);
+        assert_eq!(pallet.total_issuance(), 1000);
+
+        // 10 split 1:1:1 leaves a remainder of 1 for the heaviest, earliest collector
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(10));
+        pallet.deposit(1, 1000).unwrap();
+        pallet.set_fee_collectors(&[(7, 1), (8, 1), (9, 1)]);
+        pallet.transfer(1, 2, 100).unwrap();
+        assert_eq!([pallet.balance_of(7), pallet.balance_of(8), pallet.balance_of(9)], [4, 3, 3]);
+
+        pallet.set_fee_collectors(&[]);
+        pallet.transfer(1, 2, 100).unwrap();
+        assert_eq!(pallet.total_issuance(), 990);
+        assert_eq!(BalancesPallet::validate_event_log(&pallet.events()), Ok(()));
+
+        // A collector share that would lift the collector over the cap fails the whole transfer
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(40)).with_max_balance_per_account(100);
+        pallet.deposit(1, 100).unwrap();
+        pallet.deposit(7, 90).unwrap();
+        pallet.set_fee_collectors(&[(7, 1)]);
+        assert_eq!(pallet.transfer(1, 2, 10), Err(Error::BalanceCapExceeded));
+        assert_eq!([pallet.balance_of(1), pallet.balance_of(2), pallet.balance_of(7)], [100, 0, 90]);
+        pallet.withdraw(7, 30).unwrap();
+        pallet.transfer(1, 2, 10).unwrap();
+        assert_eq!([pallet.balance_of(1), pallet.balance_of(2), pallet.balance_of(7)], [50, 10, 100]);
+    }
+
+    #[test]
+    fn test_vesting_locks_free_balance() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        let schedule = VestingSchedule { locked: 600, per_block: 100, starting_block: 1 };
+        pallet.add_vesting_schedule(1, schedule).unwrap();
+
+        assert_eq!(pallet.spendable_balance_of(1), 400);
+        assert_eq!(pallet.transfer(1, 2, 401), Err(Error::InsufficientBalance));
+        pallet.transfer(1, 2, 400).unwrap();
+
+        pallet.next_block();
+        pallet.next_block();
+        assert_eq!(pallet.locked_balance_of(1), 500);
+        assert_eq!(pallet.withdraw(1, 101), Err(Error::InsufficientBalance));
+        pallet.withdraw(1, 100).unwrap();
+    }
+
+    #[test]
+    fn test_spendable_balance_at() {
+        let pallet = BalancesPallet::new().with_demurrage(500);
+        pallet.deposit(1, 10_000).unwrap();
+        let schedule = VestingSchedule { locked: 8000, per_block: 1000, starting_block: 2 };
+        pallet.add_vesting_schedule(1, schedule).unwrap();
+
+        let projections: Vec<Balance> = (0..=12).map(|block| pallet.spendable_balance_at(1, block).unwrap()).collect();
+        assert_eq!(pallet.block_number(), 0);
+        assert_eq!(pallet.balance_of(1), 10_000);
+        assert_eq!(projections[0], 2000);
+
+        for (block, projected) in projections.iter().enumerate().skip(1) {
+            pallet.next_block();
+            assert_eq!(pallet.block_number() as usize, block);
+            assert_eq!(pallet.spendable_balance_of(1), *projected, "block {}", block);
+        }
+        // Spendable rises while vesting outpaces demurrage, then decays once fully vested
+        assert!(projections[6] > projections[1]);
+        assert!(projections[12] < projections[10]);
+
+        // 5% of 19 rounds down to nothing, so demurrage stops there
+        assert_eq!(pallet.spendable_balance_at(1, 12 + MAX_PROJECTION_BLOCKS), Ok(19));
+        assert_eq!(pallet.spendable_balance_at(1, 13 + MAX_PROJECTION_BLOCKS), Err(Error::InvalidValue));
+        assert_eq!(pallet.spendable_balance_at(1, BlockNumber::MAX), Err(Error::InvalidValue));
+
+        let with_interest = BalancesPallet::new().with_interest_rate(300).with_demurrage(200);
+        with_interest.deposit(1, 10_000).unwrap();
+        let projected = with_interest.spendable_balance_at(1, 5).unwrap();
+        for _ in 0..5 {
+            with_interest.next_block();
+        }
+        assert_eq!(with_interest.spendable_balance_of(1), projected);
+    }
+
+    #[test]
+    fn test_batch_atomicity() {
+        let calls = [
+            Call::Transfer { to: 2, amount: 30 },
+            Call::Transfer { to: 3, amount: 500 },
+            Call::Withdraw { amount: 20 },
+        ];
+        let run = |atomicity| {
+            let pallet = BalancesPallet::new();
+            pallet.deposit(1, 100).unwrap();
+            let result = pallet.batch(1, &calls, atomicity);
+            (result.outcomes, pallet.balance_of(1), pallet.balance_of(2))
+        };
+
+        assert_eq!(
+            run(Atomicity::AllOrNothing),
+            (
+                vec![CallOutcome::RolledBack, CallOutcome::Failed(Error::InsufficientBalance), CallOutcome::Skipped],
+                100,
+                0
+            )
+        );
+        assert_eq!(
+            run(Atomicity::BestEffort),
+            (
+                vec![CallOutcome::Applied, CallOutcome::Failed(Error::InsufficientBalance), CallOutcome::Applied],
+                50,
+                30
+            )
+        );
+        assert_eq!(
+            run(Atomicity::StopOnError),
+            (
+                vec![CallOutcome::Applied, CallOutcome::Failed(Error::InsufficientBalance), CallOutcome::Skipped],
+                70,
+                30
+            )
+        );
+    }
+
+    #[test]
+    fn test_detect_anomalies() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(2, 1000).unwrap();
+        pallet.deposit(3, 1000).unwrap();
+        pallet.transfer(3, 4, 900).unwrap();
+        pallet.next_block();
+        pallet.next_block();
+        pallet.transfer(1, 4, 300).unwrap();
+        pallet.next_block();
+        pallet.transfer(1, 5, 400).unwrap();
+        pallet.transfer(2, 5, 50).unwrap();
+        pallet.deposit(2, 10).unwrap();
+
+        assert_eq!(pallet.detect_anomalies(2, 500), vec![(1, 700)]);
+        assert_eq!(pallet.detect_anomalies(1, 500), vec![]);
+        assert_eq!(pallet.detect_anomalies(3, 0), vec![(1, 700), (2, 40)]);
+        assert_eq!(pallet.detect_anomalies(4, 0), vec![]);
+        assert_eq!(pallet.detect_anomalies(0, 0), vec![]);
+    }
+
+    #[test]
+    fn test_reserve_on_deposit() {
+        let pallet = BalancesPallet::new();
+        pallet.reserve_on_deposit(1, 50);
+
+        pallet.deposit(1, 100).unwrap();
+        assert_eq!(pallet.balance_of(1), 50);
+        assert_eq!(pallet.reserved_balance_of(1), 50);
+        assert_eq!(pallet.pending_reserve_of(1), 0);
+        assert_eq!(pallet.total_issuance(), 100);
+
+        pallet.reserve_on_deposit(1, 80);
+        pallet.deposit(1, 30).unwrap();
+        pallet.deposit(1, 70).unwrap();
+        assert_eq!(pallet.balance_of(1), 70);
+        assert_eq!(pallet.reserved_balance_of(1), 130);
+        assert_eq!(BalancesPallet::validate_event_log(&pallet.events()), Ok(()));
+    }
+
+    #[test]
+    fn test_throughput() {
+        let pallet = BalancesPallet::new().with_block_time(2000);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.next_block();
+        for to in 2..=4 {
+            pallet.transfer(1, to, 10).unwrap();
+        }
+        pallet.next_block();
+        pallet.next_block();
+        pallet.withdraw(1, 10).unwrap();
+        pallet.withdraw(1, 10).unwrap();
+        pallet.withdraw(1, 10).unwrap();
+
+        assert_eq!(pallet.throughput(3), 1.0);
+        assert_eq!(pallet.throughput(4), 1.0);
+        assert_eq!(pallet.throughput(1), 1.5);
+        assert_eq!(BalancesPallet::new().throughput(3), 0.0);
+    }
+
+    #[test]
+    fn test_max_event_payload() {
+        let pallet = BalancesPallet::new().with_max_event_payload(4);
+        pallet.deposit(1, 100).unwrap();
+
+        assert_eq!(pallet.transfer_with_memo(1, 2, 10, b"hello".to_vec()), Err(Error::InvalidValue));
+        assert_eq!(pallet.balance_of(2), 0);
+        pallet.transfer_with_memo(1, 2, 10, b"rent".to_vec()).unwrap();
+        assert_eq!(pallet.balance_of(2), 10);
+        assert_eq!(pallet.events().last(), Some(&Event::Memo { from: 1, to: 2, memo: b"rent".to_vec() }));
+
+        let truncating = BalancesPallet::new()
+            .with_max_event_payload(4)
+            .with_payload_policy(PayloadPolicy::Truncate);
+        truncating.deposit(1, 100).unwrap();
+        truncating.transfer_with_memo(1, 2, 10, b"hello".to_vec()).unwrap();
+        assert_eq!(truncating.events().last(), Some(&Event::Memo { from: 1, to: 2, memo: b"hell".to_vec() }));
+    }
+
+    #[test]
+    fn test_audit_reserves() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 500).unwrap();
+        pallet.deposit(2, 500).unwrap();
+        pallet.reserve(1, 100).unwrap();
+        pallet.reserve_named(*b"staking\0", 1, 150).unwrap();
+        pallet.transfer_claimable(2, 3, 80, 4).unwrap();
+        pallet.unreserve(1, 40).unwrap();
+
+        assert_eq!(pallet.named_reserve_of(DEFAULT_RESERVE, 1), 60);
+        assert_eq!(pallet.named_reserve_of(*b"staking\0", 1), 150);
+        assert_eq!(pallet.named_reserve_of(CLAIM_RESERVE, 2), 80);
+        assert_eq!(pallet.reserved_balance_of(1), 210);
+        assert_eq!(pallet.audit_reserves(), Ok(()));
+
+        pallet.set_reserved(2, 95);
+        assert_eq!(pallet.audit_reserves(), Err(vec![(2, 95, 80)]));
+    }
+
+    #[test]
+    fn test_largest_transfer() {
+        let pallet = BalancesPallet::new();
+        assert_eq!(pallet.largest_transfer(), None);
+
+        pallet.deposit(1, 1000).unwrap();
+        pallet.transfer(1, 2, 100).unwrap();
+        pallet.next_block();
+        pallet.transfer(1, 3, 300).unwrap();
+        pallet.next_block();
+        pallet.transfer(3, 4, 300).unwrap();
+        pallet.transfer(1, 4, 50).unwrap();
+
+        assert_eq!(pallet.largest_transfer(), Some((1, 3, 300, 1)));
+    }
+
+    #[test]
+    fn test_cascade_reaping() {
+        let pallet = BalancesPallet::new().with_existential_deposit(10);
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 5).unwrap();
+        pallet.transfer(1, 3, 3).unwrap();
+        pallet.deposit(4, 50).unwrap();
+        pallet.link_accounts(1, 2);
+        pallet.link_accounts(1, 3);
+        pallet.link_accounts(1, 4);
+        assert!(pallet.account_exists(2));
+
+        pallet.withdraw(1, 88).unwrap();
+        assert!(!pallet.account_exists(1));
+        assert!(!pallet.account_exists(2));
+        assert!(!pallet.account_exists(3));
+        assert!(pallet.account_exists(4));
+        assert_eq!(pallet.total_issuance(), 50);
+        assert_eq!(pallet.accounts_by_creation(), vec![4]);
+        assert_eq!(BalancesPallet::validate_event_log(&pallet.events()), Ok(()));
+    }
+
+    #[test]
+    fn test_reaped_dust_transferred() {
+        let pallet = BalancesPallet::new()
+            .with_existential_deposit(10)
+            .with_dust_policy(DustPolicy::Transfer(9));
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 95).unwrap();
+
+        assert!(!pallet.account_exists(1));
+        assert_eq!(pallet.balance_of(9), 5);
+        assert_eq!(pallet.total_issuance(), 100);
+        assert_eq!(pallet.events().last(), Some(&Event::AccountReaped { who: 1 }));
+
+        // A destination at its cap leaves the dust to be burned
+        let pallet = BalancesPallet::new()
+            .with_existential_deposit(10)
+            .with_dust_policy(DustPolicy::Transfer(9))
+            .with_max_balance_per_account(100);
+        pallet.deposit(9, 100).unwrap();
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 95).unwrap();
+        assert!(!pallet.account_exists(1));
+        assert_eq!(pallet.balance_of(9), 100);
+        assert_eq!(pallet.total_issuance(), 195);
+        assert!(pallet.events().contains(&Event::DustLost { who: 1, amount: 5 }));
+
+        // and keeps the account while issuance is frozen
+        pallet.deposit(3, 100).unwrap();
+        pallet.freeze_issuance();
+        pallet.transfer(3, 4, 95).unwrap();
+        assert_eq!(pallet.balance_of(3), 5);
+        assert_eq!(pallet.balance_of(9), 100);
+    }
+
+    #[test]
+    fn test_issuance_from_events() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(2, 250).unwrap();
+        pallet.withdraw(1, 300).unwrap();
+        pallet.transfer(2, 3, 100).unwrap();
+        pallet.withdraw(3, 40).unwrap();
+        assert_eq!(pallet.issuance_from_events(), pallet.total_issuance() as i128);
+        assert_eq!(pallet.issuance_from_events(), 910);
+
+        let with_fees = BalancesPallet::new().with_fee(FeeConfig::Percentage(100)).with_remainder_account(9);
+        with_fees.deposit(1, 5000).unwrap();
+        with_fees.transfer(1, 2, 1234).unwrap();
+        with_fees.haircut(500).unwrap();
+        assert_eq!(with_fees.issuance_from_events(), with_fees.total_issuance() as i128);
+    }
+
+    #[test]
+    fn test_scheduled_transfers_run_by_priority() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        let low = pallet.schedule_transfer(1, 2, 70, 2, 1).unwrap();
+        pallet.schedule_transfer(1, 3, 60, 2, 5).unwrap();
+        pallet.schedule_transfer(1, 4, 10, 2, 5).unwrap();
+        assert_eq!(pallet.schedule_transfer(1, 2, 10, 0, 0), Err(Error::InvalidValue));
+
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(1), 100);
+        pallet.next_block();
+
+        let transfers: Vec<AccountId> = pallet
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Transfer { to, .. } => Some(to),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(transfers, vec![3, 4]);
+        assert_eq!(pallet.balance_of(1), 30);
+        assert_eq!(
+            pallet.events().last(),
+            Some(&Event::ScheduledTransferFailed { id: low, error: Error::InsufficientBalance })
+        );
+    }
+
+    #[test]
+    fn test_transfer_graph_dot() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(5, 10).unwrap();
+        pallet.transfer(1, 2, 100).unwrap();
+        pallet.transfer(1, 2, 50).unwrap();
+        pallet.transfer(2, 3, 20).unwrap();
+
+        assert_eq!(
+            pallet.transfer_graph_dot(),
+            "digraph transfers {\n    1;\n    2;\n    3;\n    5;\n    1 -> 2 [label=\"150\"];\n    2 -> 3 [label=\"20\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_vesting_accounts() {
+        let pallet = BalancesPallet::new();
+        for who in [3, 1, 2] {
+            pallet.deposit(who, 1000).unwrap();
+        }
+        pallet.add_vesting_schedule(3, VestingSchedule { locked: 600, per_block: 100, starting_block: 0 }).unwrap();
+        pallet.add_vesting_schedule(1, VestingSchedule { locked: 900, per_block: 50, starting_block: 1 }).unwrap();
+        pallet.add_vesting_schedule(2, VestingSchedule { locked: 200, per_block: 100, starting_block: 0 }).unwrap();
+
+        for _ in 0..3 {
+            pallet.next_block();
+        }
+        assert_eq!(pallet.vesting_accounts(), vec![(1, 800), (3, 300)]);
+    }
+
+    #[test]
+    fn test_idempotency_keys_survive_export() {
+        let pallet = BalancesPallet::new();
+        pallet.dispatch_idempotent(7, 1, Call::Deposit { amount: 100 }).unwrap();
+        assert_eq!(pallet.dispatch_idempotent(7, 1, Call::Deposit { amount: 100 }), Err(Error::DuplicateOperation));
+        // A failed call does not consume its key
+        assert_eq!(pallet.dispatch_idempotent(8, 1, Call::Withdraw { amount: 500 }), Err(Error::InsufficientBalance));
+
+        let restored = BalancesPallet::new();
+        restored.import_state(pallet.export_state()).unwrap();
+        assert_eq!(restored.dispatch_idempotent(7, 1, Call::Deposit { amount: 100 }), Err(Error::DuplicateOperation));
+        restored.dispatch_idempotent(8, 1, Call::Withdraw { amount: 40 }).unwrap();
+        assert_eq!(restored.balance_of(1), 60);
+    }
+
+    #[test]
+    fn test_overdraft_requires_collateral() {
+        let pallet = BalancesPallet::new().with_overdraft(500, 15_000);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(2, 1000).unwrap();
+        pallet.reserve(2, 400).unwrap();
+
+        pallet.reserve(1, 100).unwrap();
+        assert_eq!(pallet.withdraw(1, 1000), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.transfer(1, 3, 1000), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.balance_of(1), 900);
+
+        // 200 of debt needs 300 reserved at 150%
+        pallet.transfer(2, 1, 200).unwrap();
+        pallet.reserve(1, 200).unwrap();
+        pallet.transfer(1, 3, 1100).unwrap();
+        assert_eq!(pallet.balance_of(1), 0);
+        assert_eq!(pallet.balance_of(3), 1100);
+        assert_eq!(pallet.overdraft_of(1), 200);
+        assert_eq!(pallet.required_collateral(1), 300);
+        assert_eq!(pallet.withdraw(1, 1), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.total_issuance(), 2200);
+        assert_eq!(pallet.issuance_from_events(), 2200);
+
+        pallet.deposit(1, 50).unwrap();
+        pallet.repay_overdraft(1, 50).unwrap();
+        assert_eq!(pallet.overdraft_of(1), 150);
+        assert_eq!(pallet.required_collateral(1), 225);
+
+        // An overdrawn account stays around below the existential deposit until its debt is repaid
+        let pallet = BalancesPallet::new().with_overdraft(100, 0).with_existential_deposit(10);
+        pallet.deposit(1, 50).unwrap();
+        pallet.withdraw(1, 80).unwrap();
+        assert!(pallet.account_exists(1));
+        assert_eq!(pallet.overdraft_of(1), 30);
+        pallet.deposit(1, 35).unwrap();
+        pallet.repay_overdraft(1, 30).unwrap();
+        pallet.withdraw(1, 1).unwrap();
+        assert!(!pallet.account_exists(1));
+        assert_eq!(pallet.overdraft_of(1), 0);
+    }
+
+    #[test]
+    fn test_block_digest() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(9, 5).unwrap();
+        pallet.next_block();
+        pallet.deposit(1, 500).unwrap();
+        pallet.transfer(1, 2, 100).unwrap();
+        pallet.transfer(2, 3, 40).unwrap();
+
+        let digest = pallet.block_digest(1).unwrap();
+        assert_eq!(digest.deposited, 500);
+        assert_eq!(digest.withdrawn, 0);
+        assert_eq!(digest.transferred, 140);
+        assert_eq!(digest.event_counts, BTreeMap::from([("NewBlock", 1), ("Deposit", 1), ("Transfer", 2)]));
+        assert_eq!(digest.accounts_touched, BTreeSet::from([1, 2, 3]));
+        assert_eq!(pallet.block_digest(2), None);
+    }
+
+    #[test]
+    fn test_config_reflects_builders() {
+        let pallet = BalancesPallet::new()
+            .with_fee(FeeConfig::Flat(2))
+            .with_existential_deposit(10)
+            .with_max_transfer(1000)
+            .with_dust_policy(DustPolicy::Transfer(99));
+
+        let config = pallet.config();
+        assert_eq!(config.fee, FeeConfig::Flat(2));
+        assert_eq!(config.existential_deposit, 10);
+        assert_eq!(config.max_transfer, Some(1000));
+        assert_eq!(config.dust_policy, DustPolicy::Transfer(99));
+        assert_eq!(config.max_balance_per_account, None);
+        assert_eq!(BalancesPallet::new().config(), PalletConfig::default());
+    }
+
+    #[test]
+    fn test_rotate_author_settles_fees() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(3));
+        pallet.deposit(1, 1000).unwrap();
+        assert_eq!(pallet.rotate_author(10), 0);
+
+        pallet.transfer(1, 2, 100).unwrap();
+        pallet.transfer(1, 2, 100).unwrap();
+        assert_eq!(pallet.rotate_author(20), 6);
+        assert_eq!(pallet.balance_of(10), 6);
+        assert_eq!(pallet.author(), Some(20));
+
+        pallet.transfer(1, 2, 100).unwrap();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(20), 3);
+        assert_eq!(pallet.rotate_author(10), 0);
+        assert_eq!(pallet.total_issuance(), 1000);
+        assert!(pallet.events().contains(&Event::AuthorSettled { who: 10, amount: 6 }));
+    }
+
+    #[test]
+    fn test_rotate_author_keeps_pending_fees() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(5)).with_max_balance_per_account(1000);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.rotate_author(7);
+        pallet.transfer(1, 2, 10).unwrap();
+
+        // Fees earned while frozen stay owed to the author that earned them
+        pallet.freeze_issuance();
+        assert_eq!(pallet.rotate_author(8), 0);
+        pallet.unfreeze_issuance();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(7), 5);
+        assert_eq!(pallet.balance_of(8), 0);
+
+        // as do fees the author can't take under its cap
+        pallet.deposit(8, 998).unwrap();
+        pallet.transfer(1, 2, 10).unwrap();
+        assert_eq!(pallet.rotate_author(9), 0);
+        pallet.withdraw(8, 10).unwrap();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(8), 993);
+        assert_eq!(pallet.balance_of(9), 0);
+        assert_eq!(pallet.issuance_from_events(), to_signed(pallet.total_issuance()));
+    }
+
+    #[test]
+    fn test_mint_to_target() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 700).unwrap();
+
+        assert_eq!(pallet.mint_to_target(9, 1000), Ok(300));
+        assert_eq!(pallet.balance_of(9), 300);
+        assert_eq!(pallet.total_issuance(), 1000);
+        assert_eq!(pallet.mint_to_target(9, 1000), Ok(0));
+        assert_eq!(pallet.mint_to_target(9, 999), Err(Error::InvalidValue));
+        assert_eq!(pallet.total_issuance(), 1000);
+    }
+
+    #[test]
+    fn test_weighted_median_balance() {
+        let pallet = BalancesPallet::new();
+        assert_eq!(pallet.weighted_median_balance(), None);
+
+        for (who, amount) in [(1, 10), (2, 20), (3, 30), (4, 40), (5, 1000)] {
+            pallet.deposit(who, amount).unwrap();
+        }
+        assert_eq!(pallet.statistics().median_balance, 30);
+        assert_eq!(pallet.weighted_median_balance(), Some(1000));
+
+        let pallet = BalancesPallet::new();
+        for (who, amount) in [(1, 100), (2, 200), (3, 300), (4, 400)] {
+            pallet.deposit(who, amount).unwrap();
+        }
+        assert_eq!(pallet.statistics().median_balance, 250);
+        assert_eq!(pallet.weighted_median_balance(), Some(300));
+
+        // Reserved funds count towards their holder, so half of total issuance sits at or below 100
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.deposit(2, 100).unwrap();
+        pallet.reserve(2, 90).unwrap();
+        assert_eq!(pallet.weighted_median_balance(), Some(100));
+        pallet.deposit(3, 150).unwrap();
+        pallet.reserve(3, 150).unwrap();
+        assert_eq!(pallet.weighted_median_balance(), Some(100));
+        pallet.deposit(3, 100).unwrap();
+        assert_eq!(pallet.weighted_median_balance(), Some(250));
+    }
+
+    #[test]
+    fn test_fee_sweep() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(5)).with_fee_sweep(100);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.rotate_author(7);
+        pallet.transfer(1, 2, 10).unwrap();
+        pallet.transfer(1, 2, 10).unwrap();
+        assert_eq!(pallet.total_issuance(), 990);
+
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(100), 10);
+        assert_eq!(pallet.balance_of(7), 0);
+        assert_eq!(pallet.total_issuance(), 1000);
+        assert_eq!(pallet.account_deltas(0, 0).get(&100), Some(&10));
+
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(100), 10);
+        let swept: Vec<Event> = pallet.query_events(|event| matches!(event, Event::FeesSwept { .. }), 0, 10);
+        assert_eq!(swept, vec![Event::FeesSwept { amount: 10 }]);
+
+        // Rotating the author mid-block leaves the fees to the treasury
+        pallet.transfer(1, 2, 10).unwrap();
+        assert_eq!(pallet.rotate_author(8), 0);
+        assert_eq!(pallet.author(), Some(8));
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(7), 0);
+        assert_eq!(pallet.balance_of(100), 15);
+
+        // Fees that can't be swept stay pending until the treasury can take them
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(5)).with_fee_sweep(100).with_max_balance_per_account(100);
+        pallet.deposit(1, 100).unwrap();
+        pallet.deposit(100, 95).unwrap();
+        pallet.transfer(1, 2, 10).unwrap();
+        pallet.transfer(1, 2, 10).unwrap();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(100), 95);
+        assert_eq!(pallet.total_issuance(), 185);
+
+        pallet.withdraw(100, 10).unwrap();
+        pallet.freeze_issuance();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(100), 85);
+
+        pallet.unfreeze_issuance();
+        pallet.next_block();
+        assert_eq!(pallet.balance_of(100), 95);
+        assert_eq!(pallet.total_issuance(), 185);
+    }
+
+    #[test]
+    fn test_events_by_seq() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        pallet.transfer(1, 2, 30).unwrap();
+        pallet.withdraw(2, 10).unwrap();
+        pallet.deposit(3, 5).unwrap();
+
+        assert_eq!(
+            pallet.events_by_seq(1, 2),
+            vec![Event::Transfer { from: 1, to: 2, amount: 30 }, Event::Withdraw { who: 2, amount: 10 }]
+        );
+        assert_eq!(pallet.events_by_seq(3, 99), vec![Event::Deposit { who: 3, amount: 5 }]);
+        assert!(pallet.events_by_seq(10, 20).is_empty());
+        assert!(pallet.events_by_seq(2, 1).is_empty());
+    }
+
+    #[test]
+    fn test_net_settle() {
+        let pallet = BalancesPallet::new();
+        for who in [1, 2, 3] {
+            pallet.deposit(who, 50).unwrap();
+        }
+        let emitted_before = pallet.events().len();
+
+        let positions = pallet.net_settle(&[(1, 2, 100), (2, 3, 110), (3, 1, 90)]).unwrap();
+        assert_eq!(positions, vec![(1, -10), (2, -10), (3, 20)]);
+        assert_eq!((pallet.balance_of(1), pallet.balance_of(2), pallet.balance_of(3)), (40, 40, 70));
+        assert_eq!(pallet.events().len() - emitted_before, 3);
+        assert_eq!(pallet.total_issuance(), 150);
+
+        assert_eq!(pallet.net_settle(&[(1, 2, 41), (3, 1, 0)]), Err(Error::InsufficientBalance));
+        assert_eq!((pallet.balance_of(1), pallet.balance_of(2)), (40, 40));
+        assert_eq!(pallet.net_settle(&[(1, 2, 5), (2, 1, 5)]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_airdrop_to_tag() {
+        let pallet = BalancesPallet::new().with_max_balance_per_account(1000);
+        for who in [3, 1, 2] {
+            pallet.tag_account(who, "testers".to_string());
+        }
+        pallet.tag_account(4, "others".to_string());
+        pallet.tag_account(1, "testers".to_string());
+        assert_eq!(pallet.accounts_with_tag("testers"), vec![1, 2, 3]);
+        assert!(pallet.accounts_with_tag("unknown").is_empty());
+
+        pallet.airdrop_to_tag("testers", 100).unwrap();
+        assert_eq!([1, 2, 3, 4].map(|who| pallet.balance_of(who)), [100, 100, 100, 0]);
+        assert_eq!(pallet.total_issuance(), 300);
+
+        pallet.deposit(2, 850).unwrap();
+        assert_eq!(pallet.airdrop_to_tag("testers", 100), Err(Error::BalanceCapExceeded));
+        assert_eq!([1, 2, 3].map(|who| pallet.balance_of(who)), [100, 950, 100]);
+    }
+
+    #[test]
+    fn test_projected_balance() {
+        let pallet = BalancesPallet::new().with_fee(FeeConfig::Flat(1));
+        pallet.deposit(1, 500).unwrap();
+        pallet.deposit(2, 100).unwrap();
+        pallet.transfer_claimable(1, 2, 200, 10).unwrap();
+        pallet.schedule_transfer(2, 3, 50, 2, 0).unwrap();
+
+        assert_eq!(pallet.balance_of(2), 100);
+        assert_eq!(pallet.projected_balance(2), 249);
+        assert_eq!(pallet.projected_balance(3), 50);
+        assert_eq!(pallet.projected_balance(1), 300);
+
+        pallet.next_block();
+        pallet.next_block();
+        pallet.claim(0, 2).unwrap();
+        assert_eq!(pallet.balance_of(2), 249);
+        assert_eq!(pallet.projected_balance(2), 249);
+    }
+
+    #[test]
+    fn test_conservation_check() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+
+        let result = pallet.with_conservation_check(0, |pallet| pallet.transfer(1, 2, 40));
+        assert_eq!(result, Ok(Ok(())));
+        assert_eq!(pallet.balance_of(2), 40);
+
+        let events_before = pallet.events();
+        let result = pallet.with_conservation_check(0, |pallet| {
+            pallet.transfer(2, 3, 10).unwrap();
+            pallet.deposit(3, 5).unwrap();
+        });
+        assert_eq!(result, Err(Error::IssuanceMismatch));
+        assert_eq!((pallet.balance_of(2), pallet.balance_of(3)), (40, 0));
+        assert_eq!(pallet.total_issuance(), 100);
+        assert_eq!(pallet.events(), events_before);
+
+        assert_eq!(pallet.with_conservation_check(-15, |pallet| pallet.withdraw(1, 15).is_ok()), Ok(true));
+    }
+
+    #[test]
+    fn test_rollback_keeps_concurrent_writes() {
+        let pallet = BalancesPallet::new().with_max_balance_per_account(5000);
+        pallet.deposit(1, 1000).unwrap();
+        pallet.deposit(3, 4995).unwrap();
+
+        thread::scope(|scope| {
+            let pallet = &pallet;
+            scope.spawn(move || {
+                for _ in 0..2000 {
+                    // The fee leg overflows the fee account's cap, rolling back the price leg
+                    let result = pallet.escrow_transfer(1, 2, 3, 10, 10);
+                    assert_eq!(result, Err(Error::BalanceCapExceeded));
+                }
+            });
+            scope.spawn(move || {
+                for _ in 0..2000 {
+                    pallet.deposit(9, 1).unwrap();
+                }
+            });
+        });
+
+        assert_eq!(pallet.balance_of(9), 2000);
+        assert_eq!(pallet.balance_of(2), 0);
+        let records = pallet.event_records();
+        assert_eq!(records.iter().filter(|record| record.event == Event::Deposit { who: 9, amount: 1 }).count(), 2000);
+        assert!(records.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    }
+
+    #[test]
+    fn test_state_round_trip_keeps_claim_reserves() {
+        let pallet = BalancesPallet::new();
+        pallet.deposit(1, 100).unwrap();
+        let id = pallet.transfer_claimable(1, 2, 40, 5).unwrap();
+        pallet.reserve_named(*b"staking\0", 1, 10).unwrap();
+
+        let snapshot = pallet.export_state();
+        pallet.import_state(snapshot.clone()).unwrap();
+        assert_eq!(pallet.audit_reserves(), Ok(()));
+        pallet.claim(id, 2).unwrap();
+        assert_eq!((pallet.balance_of(1), pallet.reserved_balance_of(1), pallet.balance_of(2)), (50, 10, 40));
+        assert_eq!(pallet.named_reserve_of(*b"staking\0", 1), 10);
+
+        let restored = BalancesPallet::new();
+        restored.import_state(snapshot).unwrap();
+        assert_eq!(restored.named_reserve_of(CLAIM_RESERVE, 1), 40);
+        assert_eq!(restored.audit_reserves(), Ok(()));
+
+        // Reserves without names still land in the default reserve
+        let unnamed = StateSnapshot { reserved: HashMap::from([(3, 25)]), ..StateSnapshot::default() };
+        restored.import_state(unnamed).unwrap();
+        assert_eq!(restored.named_reserve_of(DEFAULT_RESERVE, 3), 25);
+
+        let overdrawn = StateSnapshot {
+            reserved: HashMap::from([(3, 25)]),
+            named_reserves: HashMap::from([(3, HashMap::from([(CLAIM_RESERVE, 30)]))]),
+            ..StateSnapshot::default()
+        };
+        assert_eq!(restored.import_state(overdrawn), Err(Error::InvalidValue));
+    }
+
+    #[test]
+    fn test_convert_assets() {
+        const A: AssetId = 1;
+        const B: AssetId = 2;
+        let pallet = BalancesPallet::new();
+        pallet.deposit_asset(1, A, 150).unwrap();
+        assert_eq!(pallet.convert(1, A, B, 100), Err(Error::InvalidValue));
+
+        pallet.set_conversion_rate(A, B, 2, 1).unwrap();
+        assert_eq!(pallet.convert(1, A, B, 100), Ok(200));
+        assert_eq!((pallet.asset_balance_of(1, A), pallet.asset_balance_of(1, B)), (50, 200));
+        assert_eq!((pallet.asset_issuance(A), pallet.asset_issuance(B)), (50, 200));
+        assert_eq!(pallet.convert(1, A, B, 51), Err(Error::InsufficientBalance));
+        assert_eq!(pallet.convert(1, B, A, 10), Err(Error::InvalidValue));
+        assert_eq!(pallet.set_conversion_rate(A, B, 1, 0), Err(Error::InvalidValue));
+
+        // The rate math falls back to dividing first, and reports results that cannot fit
+        pallet.set_conversion_rate(B, A, Balance::MAX / 2, Balance::MAX / 4).unwrap();
+        assert_eq!(pallet.convert(1, B, A, 200), Ok(400));
+        pallet.set_conversion_rate(A, B, Balance::MAX, 1).unwrap();
+        assert_eq!(pallet.convert(1, A, B, 2), Err(Error::Overflow));
+        assert_eq!(pallet.asset_balance_of(1, A), 450);
+        assert_eq!(mul_div(3, Balance::MAX - 1, Balance::MAX), Some(2));
+        assert_eq!(mul_div(Balance::MAX, Balance::MAX - 1, Balance::MAX), Some(Balance::MAX - 1));
+    }
+
+    #[test]
+    fn test_asset_existential_deposits() {
+        const A: AssetId = 1;
+        const B: AssetId = 2;
+        let pallet = BalancesPallet::new().with_existential_deposit(10);
+        assert_eq!(pallet.asset_existential_deposit(NATIVE_ASSET), 10);
+        assert_eq!(pallet.set_asset_existential_deposit(NATIVE_ASSET, 1), Err(Error::InvalidValue));
+        pallet.set_asset_existential_deposit(A, 50).unwrap();
+        pallet.set_asset_existential_deposit(B, 5).unwrap();
+
+        pallet.deposit_asset(1, A, 100).unwrap();
+        pallet.deposit_asset(1, B, 100).unwrap();
+        pallet.withdraw_asset(1, A, 70).unwrap();
+        pallet.withdraw_asset(1, B, 70).unwrap();
+
+        // 30 is below A's deposit of 50 but well above B's deposit of 5
+        assert_eq!(pallet.withdraw_asset(1, A, 1), Err(Error::AccountNotFound));
+        assert_eq!((pallet.asset_balance_of(1, A), pallet.asset_issuance(A)), (0, 0));
+        assert_eq!((pallet.asset_balance_of(1, B), pallet.asset_issuance(B)), (30, 30));
+        let reaped: Vec<Event> = pallet.query_events(|event| matches!(event, Event::AssetReaped { .. }), 0, 10);
+        assert_eq!(reaped, vec![Event::AssetReaped { who: 1, asset: A, dust: 30 }]);
+
+        // The native balance is reaped against the pallet-wide deposit
+        pallet.deposit(1, 15).unwrap();
+        pallet.withdraw(1, 6).unwrap();
+        assert!(!pallet.account_exists(1));
+        assert_eq!(pallet.asset_balance_of(1, B), 30);
+    }
+
+    #[test]
+    fn test_simulate_call_waits_for_transactions() {
+        let pallet = BalancesPallet::new();
+        let (started, wait_started) = std::sync::mpsc::channel();
+
+        thread::scope(|scope| {
+            let pallet = &pallet;
+            scope.spawn(move || {
+                let result: Result<(), Error> = pallet.transactional(|| {
+                    pallet.deposit(1, 100)?;
+                    started.send(()).unwrap();
+                    thread::sleep(std::time::Duration::from_millis(50));
+                    Err(Error::InvalidValue)
+                });
+                assert_eq!(result, Err(Error::InvalidValue));
+            });
+            wait_started.recv().unwrap();
+            // The preview must not start from the deposit that is about to be rolled back
+            let events = pallet.simulate_call(1, Call::Withdraw { amount: 10 });
+            assert_eq!(events, Err(Error::AccountNotFound));
+        });
     }
 }